@@ -0,0 +1,332 @@
+//! derive macros of wgpu-sandbox2, use them through `wgpu_sandbox2::layout`
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Expr, ExprLit, Field, Fields, Ident, Lit, LitInt,
+    Type,
+};
+
+/// generate `desc()` from the field types
+///
+/// - `#[vertex(instance)]` on the struct uses the instance step mode
+/// - `#[location(n)]` on a field sets its shader location, by default it follows the previous field
+/// - `#[format(Unorm8x4)]` on a field overrides the format deduced from its type
+#[proc_macro_derive(VertexLayout, attributes(vertex, location, format))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex_layout(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// check at compile time that the rust layout matches the WGSL layout of the struct
+///
+/// - `#[uniform(storage)]` on the struct checks the storage rules instead of the uniform ones
+/// - fields whose name starts with `_` are padding and don't exist on the WGSL side
+#[proc_macro_derive(GpuUniform, attributes(uniform))]
+pub fn derive_gpu_uniform(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    gpu_uniform(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn struct_fields<'a>(input: &'a DeriveInput, derive: &str) -> syn::Result<Vec<&'a Field>> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            format!("{derive} can't be derived for generic structs"),
+        ));
+    }
+    if !has_repr_c(input)? {
+        return Err(Error::new_spanned(
+            &input.ident,
+            format!("{derive} requires #[repr(C)]"),
+        ));
+    }
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                format!("{derive} requires named fields"),
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            format!("{derive} can only be derived for structs"),
+        )),
+    }
+}
+
+fn has_repr_c(input: &DeriveInput) -> syn::Result<bool> {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") || meta.path.is_ident("transparent") {
+                repr_c = true;
+            } else if meta.input.peek(syn::token::Paren) {
+                // align(N) and packed(N)
+                meta.input.parse::<proc_macro2::Group>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(repr_c)
+}
+
+fn vertex_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = struct_fields(input, "VertexLayout")?;
+
+    let mut step_mode = quote!(Vertex);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("instance") {
+                step_mode = quote!(Instance);
+                Ok(())
+            } else {
+                Err(meta.error("expected `instance`"))
+            }
+        })?;
+    }
+
+    let mut lens = vec![];
+    let mut writes = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+
+        let mut location = quote!(location);
+        let mut formats = quote!(<#ty as ::wgpu_sandbox2::layout::VertexAttributes>::FORMATS);
+        for attr in &field.attrs {
+            if attr.path().is_ident("location") {
+                let lit: LitInt = attr.parse_args()?;
+                location = quote!(#lit);
+            } else if attr.path().is_ident("format") {
+                let format: Ident = attr.parse_args()?;
+                formats = quote!(&[::wgpu_sandbox2::wgpu::VertexFormat::#format]);
+            }
+        }
+
+        lens.push(quote!((#formats).len()));
+        writes.push(quote! {
+            let formats: &[::wgpu_sandbox2::wgpu::VertexFormat] = #formats;
+            let base: u32 = #location;
+            let mut j = 0;
+            while j < formats.len() {
+                attributes[i] = ::wgpu_sandbox2::wgpu::VertexAttribute {
+                    format: formats[j],
+                    offset: ::std::mem::offset_of!(#name, #ident) as u64
+                        + j as u64 * formats[j].size(),
+                    shader_location: base + j as u32,
+                };
+                i += 1;
+                j += 1;
+            }
+            location = base + formats.len() as u32;
+        });
+    }
+
+    let duplicate = format!("duplicate shader location in the VertexLayout of `{name}`");
+
+    Ok(quote! {
+        impl ::wgpu_sandbox2::layout::VertexLayout for #name {
+            const STEP_MODE: ::wgpu_sandbox2::wgpu::VertexStepMode =
+                ::wgpu_sandbox2::wgpu::VertexStepMode::#step_mode;
+
+            #[allow(unused_assignments)]
+            const ATTRIBUTES: &'static [::wgpu_sandbox2::wgpu::VertexAttribute] = {
+                const LEN: usize = 0 #(+ #lens)*;
+                const ATTRIBUTES: [::wgpu_sandbox2::wgpu::VertexAttribute; LEN] = {
+                    let mut attributes = [::wgpu_sandbox2::wgpu::VertexAttribute {
+                        format: ::wgpu_sandbox2::wgpu::VertexFormat::Float32,
+                        offset: 0,
+                        shader_location: 0,
+                    }; LEN];
+                    let mut i = 0;
+                    let mut location = 0u32;
+                    #({ #writes })*
+
+                    let mut a = 0;
+                    while a < LEN {
+                        let mut b = a + 1;
+                        while b < LEN {
+                            assert!(
+                                attributes[a].shader_location != attributes[b].shader_location,
+                                #duplicate
+                            );
+                            b += 1;
+                        }
+                        a += 1;
+                    }
+                    attributes
+                };
+                &ATTRIBUTES
+            };
+        }
+
+        impl #name {
+            pub fn desc() -> ::wgpu_sandbox2::wgpu::VertexBufferLayout<'static> {
+                <Self as ::wgpu_sandbox2::layout::VertexLayout>::desc()
+            }
+        }
+    })
+}
+
+/// how the WGSL layout of a field type is known
+enum WgslField<'a> {
+    /// scalar, vector, matrix or struct implementing WgslType
+    Type(&'a Type),
+    /// `array<E, N>`
+    Array(&'a Type, &'a Expr),
+}
+
+fn wgsl_field(ty: &Type) -> WgslField<'_> {
+    let Type::Array(array) = ty else {
+        return WgslField::Type(ty);
+    };
+    let len = match &array.len {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(len), ..
+        }) => len.base10_parse::<usize>().ok(),
+        _ => None,
+    };
+    // [f32; 2..=4] are vectors and [[f32; 4]; 4] is a matrix
+    let vector = |ty: &Type, len: Option<usize>| {
+        let scalar = matches!(ty, Type::Path(p) if ["f32", "u32", "i32"]
+            .iter()
+            .any(|s| p.path.is_ident(s)));
+        scalar && matches!(len, Some(2..=4))
+    };
+    let matrix = match &*array.elem {
+        Type::Array(inner) => {
+            let inner_len = match &inner.len {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(len), ..
+                }) => len.base10_parse::<usize>().ok(),
+                _ => None,
+            };
+            vector(&inner.elem, inner_len) && inner_len == Some(4) && len == Some(4)
+        }
+        _ => false,
+    };
+    if vector(&array.elem, len) || matrix {
+        WgslField::Type(ty)
+    } else {
+        WgslField::Array(&array.elem, &array.len)
+    }
+}
+
+fn gpu_uniform(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = struct_fields(input, "GpuUniform")?;
+
+    let mut uniform = true;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("uniform")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("storage") {
+                uniform = false;
+                Ok(())
+            } else {
+                Err(meta.error("expected `storage`"))
+            }
+        })?;
+    }
+    let rules = if uniform { "uniform" } else { "storage" };
+
+    let mut checks = vec![];
+    let mut members = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        if ident.to_string().starts_with('_') {
+            continue;
+        }
+
+        let misplaced = format!(
+            "field `{ident}` of `{name}` is not at the offset required by the WGSL {rules} layout, add padding before it"
+        );
+        let (layout, wgsl_type) = match wgsl_field(&field.ty) {
+            WgslField::Type(ty) => (
+                quote! {
+                    let size = <#ty as WgslType>::SIZE;
+                    let is_struct = <#ty as WgslType>::IS_STRUCT;
+                    let align = if UNIFORM && is_struct {
+                        align_to(<#ty as WgslType>::ALIGN, 16)
+                    } else {
+                        <#ty as WgslType>::ALIGN
+                    };
+                },
+                quote!(<#ty as WgslType>::NAME.to_string()),
+            ),
+            WgslField::Array(elem, len) => {
+                let stride = format!(
+                    "the elements of field `{ident}` of `{name}` don't have the array stride required by the WGSL {rules} layout"
+                );
+                (
+                    quote! {
+                        let mut align = <#elem as WgslType>::ALIGN;
+                        if UNIFORM {
+                            align = align_to(align, 16);
+                        }
+                        let stride = align_to(<#elem as WgslType>::SIZE, align);
+                        assert!(::std::mem::size_of::<#elem>() == stride, #stride);
+                        let size = stride * #len;
+                        let is_struct = false;
+                    },
+                    quote!(format!("array<{}, {}>", <#elem as WgslType>::NAME, #len)),
+                )
+            }
+        };
+
+        checks.push(quote! {
+            #layout
+            offset = align_to(offset, align);
+            assert!(::std::mem::offset_of!(#name, #ident) == offset, #misplaced);
+            offset += size;
+            if UNIFORM && is_struct {
+                offset = align_to(offset, 16);
+            }
+            if align > struct_align {
+                struct_align = align;
+            }
+        });
+        let member = ident.to_string();
+        members.push(quote!((#member, #wgsl_type)));
+    }
+
+    let size = format!(
+        "the size of `{name}` doesn't match the WGSL {rules} layout, add padding at the end"
+    );
+    let wgsl_name = name.to_string();
+
+    Ok(quote! {
+        impl ::wgpu_sandbox2::layout::WgslType for #name {
+            #[allow(unused_assignments, unused_variables)]
+            const ALIGN: usize = {
+                use ::wgpu_sandbox2::layout::{align_to, WgslType};
+                const UNIFORM: bool = #uniform;
+                let mut offset = 0;
+                let mut struct_align = 1;
+                #({ #checks })*
+                assert!(::std::mem::size_of::<#name>() == align_to(offset, struct_align), #size);
+                struct_align
+            };
+            const SIZE: usize = ::std::mem::size_of::<#name>();
+            const NAME: &'static str = #wgsl_name;
+            const IS_STRUCT: bool = true;
+        }
+
+        impl ::wgpu_sandbox2::layout::GpuUniform for #name {
+            fn wgsl_members() -> Vec<(&'static str, String)> {
+                use ::wgpu_sandbox2::layout::WgslType;
+                vec![#(#members),*]
+            }
+        }
+
+        // evaluate the layout checks even when the struct is never nested
+        const _: () = assert!(<#name as ::wgpu_sandbox2::layout::WgslType>::ALIGN > 0);
+    })
+}
@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use glam::{Mat4, Quat, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::gltf_scene::GltfScene;
+
+/// WGSL helper computing the skinning matrix of a vertex
+/// the user shader declares the joint matrices storage binding, for example
+/// `@group(1) @binding(0) var<storage, read> joint_matrices: array<mat4x4<f32>>;`
+pub const SKINNING_WGSL: &str = r#"
+fn skin_matrix(joints: vec4<u32>, weights: vec4<f32>) -> mat4x4<f32> {
+    return joint_matrices[joints.x] * weights.x
+        + joint_matrices[joints.y] * weights.y
+        + joint_matrices[joints.z] * weights.z
+        + joint_matrices[joints.w] * weights.w;
+}
+"#;
+
+/// joints of a skinned mesh
+#[derive(Debug, Clone)]
+pub struct Skin {
+    pub name: Option<String>,
+    /// node indices of the joints
+    pub joints: Vec<usize>,
+    /// one per joint
+    pub inverse_bind_matrices: Vec<Mat4>,
+    /// node index of the skeleton root
+    pub skeleton: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    /// values are stored as (in tangent, value, out tangent)
+    CubicSpline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+/// keyframes animating a property of a node
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// keyframe times in seconds
+    pub times: Vec<f32>,
+    /// flattened keyframe values, `width` floats per value
+    pub values: Vec<f32>,
+    /// 3 for translation and scale, 4 for rotation, number of morph targets for weights
+    pub width: usize,
+}
+
+impl Channel {
+    /// value of the property at time t (clamped to the keyframes)
+    pub fn sample(&self, t: f32) -> Vec<f32> {
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let stride = if cubic { 3 * self.width } else { self.width };
+        // offset of the value itself inside a keyframe
+        let value_offset = if cubic { self.width } else { 0 };
+        let value = |k: usize| {
+            let start = k * stride + value_offset;
+            &self.values[start..start + self.width]
+        };
+
+        let last = self.times.len() - 1;
+        if self.times.len() == 1 || t <= self.times[0] {
+            return value(0).to_vec();
+        }
+        if t >= self.times[last] {
+            return value(last).to_vec();
+        }
+
+        let k = self.times.partition_point(|&time| time <= t) - 1;
+        let dt = self.times[k + 1] - self.times[k];
+        let s = (t - self.times[k]) / dt;
+
+        let mut result = match self.interpolation {
+            Interpolation::Step => value(k).to_vec(),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let a = Quat::from_slice(value(k));
+                let b = Quat::from_slice(value(k + 1));
+                return a.slerp(b, s).to_array().to_vec();
+            }
+            Interpolation::Linear => value(k)
+                .iter()
+                .zip(value(k + 1))
+                .map(|(a, b)| a + (b - a) * s)
+                .collect(),
+            Interpolation::CubicSpline => {
+                let out_tangent = &self.values[k * stride + 2 * self.width..(k + 1) * stride];
+                let in_tangent = &self.values[(k + 1) * stride..(k + 1) * stride + self.width];
+                let (s2, s3) = (s * s, s * s * s);
+                (0..self.width)
+                    .map(|i| {
+                        (2.0 * s3 - 3.0 * s2 + 1.0) * value(k)[i]
+                            + (s3 - 2.0 * s2 + s) * dt * out_tangent[i]
+                            + (-2.0 * s3 + 3.0 * s2) * value(k + 1)[i]
+                            + (s3 - s2) * dt * in_tangent[i]
+                    })
+                    .collect()
+            }
+        };
+
+        if self.property == Property::Rotation {
+            result = Quat::from_slice(&result).normalize().to_array().to_vec();
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    /// time of the last keyframe in seconds
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    /// set the node properties of the scene to their value at time t
+    /// the world transforms are not updated
+    pub fn apply(&self, scene: &mut GltfScene, t: f32) {
+        for channel in &self.channels {
+            let value = channel.sample(t);
+            let node = &mut scene.nodes[channel.node];
+            match channel.property {
+                Property::Translation => node.translation = Vec3::from_slice(&value),
+                Property::Rotation => node.rotation = Quat::from_slice(&value),
+                Property::Scale => node.scale = Vec3::from_slice(&value),
+                Property::MorphWeights => node.weights = value,
+            }
+        }
+    }
+}
+
+/// joint matrices of a skin stored in a storage buffer
+#[derive(Debug)]
+pub struct JointBuffer {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// plays the animation clips of a scene and uploads the joint matrices
+/// the bind group layout has the joint matrices at binding 0, visible in the vertex stage
+#[derive(Debug)]
+pub struct AnimationPlayer {
+    /// index in GltfScene::animations
+    pub clip: Option<usize>,
+    /// current time in seconds
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    /// one per skin of the scene
+    pub joint_buffers: Vec<JointBuffer>,
+}
+
+impl AnimationPlayer {
+    pub fn new(device: &wgpu::Device, scene: &GltfScene) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("joints_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let joint_buffers = (0..scene.skins.len())
+            .map(|skin| {
+                let mut matrices = scene.joint_matrices(skin);
+                // a storage binding can't be empty, a skin without joints gets an identity
+                if matrices.is_empty() {
+                    matrices.push(Mat4::IDENTITY);
+                }
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("joints_buffer"),
+                    contents: bytemuck::cast_slice(&matrices),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("joints_bind_group"),
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                JointBuffer { buffer, bind_group }
+            })
+            .collect();
+
+        Self {
+            clip: None,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: false,
+            bind_group_layout,
+            joint_buffers,
+        }
+    }
+
+    /// start a clip from the beginning
+    pub fn play(&mut self, clip: usize) {
+        self.clip = Some(clip);
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    /// advance the time, pose the scene and upload the joint matrices
+    pub fn update(&mut self, scene: &mut GltfScene, queue: &wgpu::Queue, dt: Duration) {
+        let Some(index) = self.clip else {
+            return;
+        };
+        // the clips are moved out while the scene is posed
+        let animations = std::mem::take(&mut scene.animations);
+        if let Some(clip) = animations.get(index) {
+            if self.playing {
+                self.time += dt.as_secs_f32() * self.speed;
+                if self.looping && clip.duration > 0.0 {
+                    self.time = self.time.rem_euclid(clip.duration);
+                } else {
+                    self.time = self.time.clamp(0.0, clip.duration);
+                    let end = if self.speed < 0.0 { 0.0 } else { clip.duration };
+                    if self.time == end {
+                        self.playing = false;
+                    }
+                }
+            }
+            self.evaluate(clip, scene, queue);
+        }
+        scene.animations = animations;
+    }
+
+    /// pose the scene with the clip at the current time and upload the joint matrices
+    pub fn evaluate(&self, clip: &AnimationClip, scene: &mut GltfScene, queue: &wgpu::Queue) {
+        clip.apply(scene, self.time);
+        scene.update_transforms();
+        for (skin, joints) in self.joint_buffers.iter().enumerate() {
+            let matrices = scene.joint_matrices(skin);
+            queue.write_buffer(&joints.buffer, 0, bytemuck::cast_slice(&matrices));
+        }
+    }
+}
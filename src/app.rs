@@ -5,16 +5,24 @@ use egui_wgpu::renderer::ScreenDescriptor;
 
 use futures_lite::future::block_on;
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     window::WindowBuilder,
 };
 
+use crate::blit::{BlitMode, BlitScaling};
+use crate::camera::CameraUniform;
 use crate::gpu::{Gpu, GpuBuilder};
+use crate::grid::{GridConfig, GridRenderer};
+use crate::logging::LoggingConfig;
+use crate::render_target::{RenderTarget, RenderTargetBuilder, RenderTargetSize, RenderTargets};
+use crate::tonemap::{TonemapRenderer, Tonemapping, HDR_FORMAT};
 
 #[cfg(feature = "egui")]
-use crate::egui_renderer::EguiRenderer;
+use crate::egui_renderer::{EguiRenderer, Toasts};
+#[cfg(feature = "egui")]
+use crate::logging::LogConsole;
 
 pub trait AppInstance {
     /// create an isntance of the app
@@ -27,6 +35,10 @@ pub trait AppInstance {
     fn update(&mut self, _gpu: &Gpu, _dt: Duration) {}
 
     /// render the current frame
+    ///
+    /// `frame_view` has the size of Gpu::scene_size(), which differs from the surface with a
+    /// render scale or an internal resolution: size the depth and the other attachments of the
+    /// pass from scene_size() (or use a RenderTarget), not from surface_size()
     fn render(
         &self,
         gpu: &Gpu,
@@ -45,7 +57,7 @@ pub trait AppInstance {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
                     },
-                    view: &frame_view,
+                    view: frame_view,
                     resolve_target: None,
                 })],
                 ..Default::default()
@@ -56,6 +68,36 @@ pub trait AppInstance {
         None
     }
 
+    /// render the current frame when the app owns a depth target (see App::with_grid)
+    ///
+    /// the depth is cleared to 1.0 before the call, draw the scene with it as depth attachment
+    /// (format SCENE_DEPTH_FORMAT) so the overlays of the app are hidden behind the scene
+    fn render_with_depth(
+        &self,
+        gpu: &Gpu,
+        frame_view: &wgpu::TextureView,
+        _depth_view: &wgpu::TextureView,
+    ) -> Option<Vec<wgpu::CommandBuffer>> {
+        self.render(gpu, frame_view)
+    }
+
+    /// render targets of the app, registered after create and resized with the surface
+    ///
+    /// the targets created later follow the scene size with RenderTarget::resize
+    fn render_targets(&self) -> Vec<RenderTarget> {
+        Vec::new()
+    }
+
+    /// camera used by the overlays drawn by the app, such as the grid
+    fn camera(&self) -> Option<CameraUniform> {
+        None
+    }
+
+    /// exposure and tonemapper applied to the scene when the app is hdr
+    fn tonemapping(&self) -> Tonemapping {
+        Tonemapping::default()
+    }
+
     /// destroy the app
     fn destroy(&self) {}
 
@@ -63,6 +105,60 @@ pub trait AppInstance {
     fn run_egui(&self, ctx: &egui::Context);
 }
 
+/// format of the scene depth target owned by the app
+pub const SCENE_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// environment variable overriding the wgpu api trace directory
+pub const TRACE_DIR_ENV: &str = "WGPU_SANDBOX_TRACE_DIR";
+
+/// command line argument overriding the wgpu api trace directory
+pub const TRACE_DIR_ARG: &str = "--trace-dir";
+
+/// read the trace directory from the command line (`--trace-dir <path>` or
+/// `--trace-dir=<path>`), then from the environment
+fn trace_dir_override() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == TRACE_DIR_ARG {
+            return args.next();
+        }
+        if let Some(dir) = arg
+            .strip_prefix(TRACE_DIR_ARG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return Some(dir.to_owned());
+        }
+    }
+    std::env::var(TRACE_DIR_ENV).ok()
+}
+
+/// map the window sizes and cursor positions to the internal resolution
+fn to_internal(gpu: &Gpu, upscale: &BlitMode, event: &WindowEvent) -> Option<WindowEvent<'static>> {
+    let scene = gpu.scene_size();
+    match event {
+        WindowEvent::Resized(_) => Some(WindowEvent::Resized(PhysicalSize::new(scene.0, scene.1))),
+        #[allow(deprecated)]
+        WindowEvent::CursorMoved {
+            device_id,
+            position,
+            modifiers,
+        } => {
+            // inverse of the placement of the blit
+            let (viewport, uv) = upscale.placement(scene, gpu.surface_size());
+            let map = |p: f64, i: usize| {
+                let t = (p as f32 - viewport[i]) / viewport[i + 2];
+                ((uv[i] + t * uv[i + 2]) * [scene.0, scene.1][i] as f32) as f64
+            };
+            Some(WindowEvent::CursorMoved {
+                device_id: *device_id,
+                position: PhysicalPosition::new(map(position.x, 0), map(position.y, 1)),
+                modifiers: *modifiers,
+            })
+        }
+        _ => None,
+    }
+}
+
 /// builder for the struct App
 #[derive(Debug, Clone)]
 pub struct AppBuilder {
@@ -72,12 +168,18 @@ pub struct AppBuilder {
     dim: (u32, u32),
     /// builder for the gpu absatraction
     gpu_builder: GpuBuilder,
-    /// if true, init logging for wgpu
-    init_subscriber: bool,
+    /// logger installed when the app is built, None to keep the current one
+    logging: Option<LoggingConfig>,
     /// set if the window of the app is resizable
     resizable: bool,
     /// enale exiting the app with the escape key
     esc: bool,
+    /// render the scene in an Rgba16Float target, tonemapped to the surface
+    hdr: bool,
+    /// internal resolution of the scene, upscaled to the surface
+    render_size: Option<RenderTargetSize>,
+    /// nearest filtering of the upscale, integer scaling of a fixed resolution
+    pixel_art: bool,
 }
 
 impl AppBuilder {
@@ -106,8 +208,15 @@ impl AppBuilder {
     }
 
     /// boolean to set if you want to enable wgpu logging
+    /// (installs the default LoggingConfig)
     pub fn with_init_subscriber(mut self, value: bool) -> Self {
-        self.init_subscriber = value;
+        self.logging = value.then(LoggingConfig::default);
+        self
+    }
+
+    /// change the logger installed when the app is built
+    pub fn with_logging(mut self, config: LoggingConfig) -> Self {
+        self.logging = Some(config);
         self
     }
 
@@ -117,6 +226,39 @@ impl AppBuilder {
         self
     }
 
+    /// render into an Rgba16Float scene target (see Gpu::get_scene_format), tonemapped to
+    /// the surface with AppInstance::tonemapping before egui
+    pub fn with_hdr(mut self, value: bool) -> Self {
+        self.hdr = value;
+        self
+    }
+
+    /// render the scene at a fraction (or a multiple) of the surface size, scaled to the
+    /// surface after the tonemapping
+    ///
+    /// the scale must be finite and positive, the internal size is capped by the texture limit
+    /// of the device
+    pub fn with_render_scale(mut self, scale: f32) -> Self {
+        assert!(
+            scale.is_finite() && scale > 0.0,
+            "invalid render scale {scale}"
+        );
+        self.render_size = Some(RenderTargetSize::Scaled(scale));
+        self
+    }
+
+    /// render the scene at a fixed size, fitted to the surface with bars on the sides
+    pub fn with_fixed_internal_resolution(mut self, width: u32, height: u32) -> Self {
+        self.render_size = Some(RenderTargetSize::Fixed(width, height));
+        self
+    }
+
+    /// scale the internal resolution with nearest filtering, by integer factors when it is fixed
+    pub fn with_pixel_art(mut self, value: bool) -> Self {
+        self.pixel_art = value;
+        self
+    }
+
     /// set if the app should exit when escape key is pressed
     pub fn with_esc(mut self, esc: bool) -> Self {
         self.esc = esc;
@@ -125,9 +267,14 @@ impl AppBuilder {
 
     /// build the app
     pub fn build(&self) -> App {
-        if self.init_subscriber {
-            env_logger::init();
-        }
+        #[cfg_attr(not(feature = "egui"), allow(unused_variables))]
+        let log_buffer = match &self.logging {
+            Some(config) => config.init().unwrap_or_else(|_| {
+                log::warn!("a logger is already installed, the logging config is ignored");
+                None
+            }),
+            None => None,
+        };
 
         let event_loop = EventLoop::new();
         let window = WindowBuilder::new()
@@ -137,19 +284,62 @@ impl AppBuilder {
             .build(&event_loop)
             .unwrap();
 
-        let gpu = block_on(self.gpu_builder.build(&window));
+        let gpu_builder = match trace_dir_override() {
+            Some(dir) => self.gpu_builder.clone().with_trace_dir(dir),
+            None => self.gpu_builder.clone(),
+        };
+        let mut gpu = block_on(gpu_builder.build(&window))
+            .unwrap_or_else(|e| panic!("failed to build the gpu: {e}"));
+        if let Some(size) = self.render_size {
+            gpu.render_size = size;
+        }
+        let tonemap = self.hdr.then(|| {
+            gpu.scene_format = Some(HDR_FORMAT);
+            TonemapRenderer::new(&gpu)
+        });
+
+        // the scene ends in the surface format, before being scaled to the surface
+        let internal = self.render_size.map(|_| {
+            RenderTargetBuilder::new()
+                .with_color(gpu.get_surface_texture_format())
+                .build(&gpu)
+        });
+        let upscale = BlitMode::new(match self.render_size {
+            Some(RenderTargetSize::Fixed(..)) if self.pixel_art => BlitScaling::Integer,
+            Some(RenderTargetSize::Fixed(..)) => BlitScaling::Fit,
+            _ => BlitScaling::Stretch,
+        })
+        .with_filter(match self.pixel_art {
+            true => wgpu::FilterMode::Nearest,
+            false => wgpu::FilterMode::Linear,
+        });
 
         #[cfg(feature = "egui")]
         let renderer = EguiRenderer::new(&gpu.device, gpu.surface_config.format, None, 1, &window);
 
+        let mut render_targets = RenderTargets::default();
+        for target in internal.iter().chain(tonemap.as_ref().map(|t| &t.target)) {
+            render_targets.register(target);
+        }
+
         App {
             window,
             event_loop,
             gpu,
+            render_targets,
             esc: self.esc,
+            grid: None,
+            depth: None,
+            tonemap,
+            internal,
+            upscale,
 
             #[cfg(feature = "egui")]
             egui_renderer: renderer,
+            #[cfg(feature = "egui")]
+            toasts: Toasts::new(Duration::from_secs(5)),
+            #[cfg(feature = "egui")]
+            log_console: log_buffer.map(LogConsole::new),
         }
     }
 }
@@ -160,9 +350,12 @@ impl Default for AppBuilder {
             name: String::from("default app"),
             dim: (640, 360),
             gpu_builder: GpuBuilder::default(),
-            init_subscriber: true,
+            logging: Some(LoggingConfig::default()),
             resizable: false,
             esc: true,
+            hdr: false,
+            render_size: None,
+            pixel_art: false,
         }
     }
 }
@@ -171,16 +364,51 @@ pub struct App {
     window: winit::window::Window,
     event_loop: EventLoop<()>,
     gpu: Gpu,
+    /// targets resized after the surface
+    render_targets: RenderTargets,
     esc: bool,
+    grid: Option<GridRenderer>,
+    /// scene depth shared by the app instance and the grid
+    depth: Option<RenderTarget>,
+    tonemap: Option<TonemapRenderer>,
+    /// scene target at the internal resolution
+    internal: Option<RenderTarget>,
+    upscale: BlitMode,
 
     #[cfg(feature = "egui")]
     egui_renderer: EguiRenderer,
+    #[cfg(feature = "egui")]
+    toasts: Toasts,
+    #[cfg(feature = "egui")]
+    log_console: Option<LogConsole>,
 }
 
 impl App {
+    /// draw an infinite ground grid in each frame, seen from AppInstance::camera
+    ///
+    /// the app then owns a scene depth target, given to AppInstance::render_with_depth, and the
+    /// grid is depth tested against it
+    pub fn with_grid(mut self, config: GridConfig) -> Self {
+        self.grid = Some(GridRenderer::with_formats(
+            &self.gpu,
+            config,
+            self.gpu.get_scene_format(),
+            Some(SCENE_DEPTH_FORMAT),
+        ));
+        let depth = RenderTargetBuilder::new()
+            .with_depth(SCENE_DEPTH_FORMAT)
+            .build(&self.gpu);
+        self.render_targets.register(&depth);
+        self.depth = Some(depth);
+        self
+    }
+
     pub fn run<T: AppInstance + 'static>(mut self) {
         // build app
         let mut instance = T::create(&self.gpu);
+        for target in instance.render_targets() {
+            self.render_targets.register(&target);
+        }
 
         let mut last_frame = Instant::now();
 
@@ -189,27 +417,49 @@ impl App {
                 Event::WindowEvent { ref event, .. } => {
                     match event {
                         WindowEvent::CloseRequested => control_flow.set_exit(),
-                        WindowEvent::KeyboardInput { input, .. } => match input {
-                            KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                state: ElementState::Pressed,
-                                ..
-                            } => {
-                                if self.esc {
-                                    control_flow.set_exit();
-                                }
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::Escape),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        } if self.esc => control_flow.set_exit(),
+                        // toggle the log console
+                        #[cfg(feature = "egui")]
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::F12),
+                                    state: ElementState::Pressed,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            if let Some(console) = &mut self.log_console {
+                                console.open = !console.open;
                             }
-                            _ => (),
-                        },
+                        }
                         // resize the surface
                         WindowEvent::Resized(size) => {
                             self.gpu.resize_surface((size.width, size.height));
+                            self.render_targets.resize(&self.gpu);
+                            if let Some(tonemap) = &mut self.tonemap {
+                                tonemap.resize(&self.gpu);
+                            }
                         }
                         _ => (),
                     }
 
-                    // send the event to the app
-                    instance.events(event);
+                    // send the event to the app, in the space of the internal resolution
+                    match &self.internal {
+                        Some(_) => match to_internal(&self.gpu, &self.upscale, event) {
+                            Some(event) => instance.events(&event),
+                            None => instance.events(event),
+                        },
+                        None => instance.events(event),
+                    }
 
                     #[cfg(feature = "egui")]
                     self.egui_renderer.handle_input(event);
@@ -228,14 +478,119 @@ impl App {
                                 .texture
                                 .create_view(&wgpu::TextureViewDescriptor::default());
 
-                            let cmd_bufs =
-                                instance.render(&self.gpu, &frame_view).unwrap_or(vec![]);
+                            // the scene is resolved to the internal target when there is one
+                            let internal = self.internal.as_ref().map(|target| target.textures());
+                            let output_view = match &internal {
+                                Some(textures) => textures.view(0),
+                                None => &frame_view,
+                            };
+
+                            // the app draws to the hdr target when there is one
+                            let scene_view = match &self.tonemap {
+                                Some(tonemap) => tonemap.view(),
+                                None => output_view,
+                            };
+
+                            let depth = self.depth.as_ref().map(|target| target.textures());
+                            let cmd_bufs = match depth.as_ref().and_then(|d| d.depth_view()) {
+                                Some(depth_view) => {
+                                    // the clear is submitted on its own, the instance may submit
+                                    // its passes itself (like the default render) and those
+                                    // load the depth, so it can't wait for the grid encoder
+                                    let mut encoder = self.gpu.device.create_command_encoder(
+                                        &wgpu::CommandEncoderDescriptor {
+                                            label: Some("depth_clear_command_encoder"),
+                                        },
+                                    );
+                                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                                        label: Some("depth_clear_render_pass"),
+                                        depth_stencil_attachment: depth.as_ref().and_then(|d| {
+                                            d.depth_attachment(wgpu::LoadOp::Clear(1.0))
+                                        }),
+                                        ..Default::default()
+                                    });
+                                    self.gpu.queue.submit(std::iter::once(encoder.finish()));
+                                    instance.render_with_depth(&self.gpu, scene_view, depth_view)
+                                }
+                                None => instance.render(&self.gpu, scene_view),
+                            };
 
-                            self.gpu.queue.submit(cmd_bufs.into_iter());
+                            self.gpu.queue.submit(cmd_bufs.unwrap_or_default());
+
+                            // draw the grid after the scene, depth tested against it
+                            if let (Some(grid), Some(camera)) = (&self.grid, instance.camera()) {
+                                grid.prepare(&self.gpu, &camera);
+                                let mut encoder = self.gpu.device.create_command_encoder(
+                                    &wgpu::CommandEncoderDescriptor {
+                                        label: Some("grid_command_encoder"),
+                                    },
+                                );
+                                {
+                                    let mut rpass =
+                                        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                                            label: Some("grid_render_pass"),
+                                            color_attachments: &[Some(
+                                                wgpu::RenderPassColorAttachment {
+                                                    view: scene_view,
+                                                    resolve_target: None,
+                                                    ops: wgpu::Operations {
+                                                        load: wgpu::LoadOp::Load,
+                                                        store: wgpu::StoreOp::Store,
+                                                    },
+                                                },
+                                            )],
+                                            depth_stencil_attachment: depth.as_ref().and_then(
+                                                |d| d.depth_attachment(wgpu::LoadOp::Load),
+                                            ),
+                                            ..Default::default()
+                                        });
+                                    grid.render(&mut rpass);
+                                }
+                                self.gpu.queue.submit(std::iter::once(encoder.finish()));
+                            }
+
+                            // resolve the hdr target to the surface
+                            if let Some(tonemap) = &self.tonemap {
+                                let mut encoder = self.gpu.device.create_command_encoder(
+                                    &wgpu::CommandEncoderDescriptor {
+                                        label: Some("tonemap_command_encoder"),
+                                    },
+                                );
+                                tonemap.render(
+                                    &self.gpu,
+                                    &mut encoder,
+                                    output_view,
+                                    &instance.tonemapping(),
+                                );
+                                self.gpu.queue.submit(std::iter::once(encoder.finish()));
+                            }
+
+                            // scale the internal resolution to the surface
+                            if let Some(textures) = &internal {
+                                let mut encoder = self.gpu.device.create_command_encoder(
+                                    &wgpu::CommandEncoderDescriptor {
+                                        label: Some("upscale_command_encoder"),
+                                    },
+                                );
+                                self.gpu.blit_to(
+                                    &mut encoder,
+                                    &textures.colors[0],
+                                    &frame_view,
+                                    self.gpu.get_surface_texture_format(),
+                                    self.gpu.surface_size(),
+                                    self.upscale,
+                                );
+                                self.gpu.queue.submit(std::iter::once(encoder.finish()));
+                            }
 
                             // draw egui
                             #[cfg(feature = "egui")]
                             {
+                                // errors collected by the gpu are shown as toasts
+                                for e in self.gpu.take_errors() {
+                                    self.toasts.push(e);
+                                }
+
                                 let screen_desc = ScreenDescriptor {
                                     size_in_pixels: [
                                         self.gpu.surface_config.width,
@@ -257,7 +612,13 @@ impl App {
                                     &self.window,
                                     &frame_view,
                                     screen_desc,
-                                    |ui| instance.run_egui(ui),
+                                    |ctx| {
+                                        instance.run_egui(ctx);
+                                        self.toasts.show(ctx);
+                                        if let Some(console) = &mut self.log_console {
+                                            console.show(ctx);
+                                        }
+                                    },
                                 );
                                 self.gpu
                                     .queue
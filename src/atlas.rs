@@ -0,0 +1,522 @@
+use std::fmt::Display;
+
+use crate::graphics::{Rect, Texture, TextureBuilder};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtlasError {
+    /// the image with its padding and extrusion doesn't fit in an empty page
+    TooLarge { width: u32, height: u32 },
+    /// the data length doesn't match the image size
+    DataSize { expected: usize, actual: usize },
+    /// a layout and its images don't match
+    MissingImage(usize),
+}
+
+impl Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasError::TooLarge { width, height } => {
+                write!(f, "image of {width}x{height} doesn't fit in an atlas page")
+            }
+            AtlasError::DataSize { expected, actual } => {
+                write!(f, "expected {expected} bytes of image data, got {actual}")
+            }
+            AtlasError::MissingImage(index) => write!(f, "no image data for atlas entry {index}"),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PackingAlgorithm {
+    /// bottom-left skyline, dense for images of mixed sizes
+    #[default]
+    Skyline,
+    /// rows of images, fast and good for images of similar heights such as glyphs
+    Shelf,
+}
+
+/// top of the skyline over a range of columns
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// row of images
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shelf {
+    y: u32,
+    height: u32,
+    cursor: u32,
+}
+
+/// free space of a page
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PageAllocator {
+    Skyline(Vec<Segment>),
+    Shelf(Vec<Shelf>),
+}
+
+impl PageAllocator {
+    fn new(algorithm: PackingAlgorithm, width: u32) -> Self {
+        match algorithm {
+            PackingAlgorithm::Skyline => Self::Skyline(vec![Segment { x: 0, y: 0, width }]),
+            PackingAlgorithm::Shelf => Self::Shelf(vec![]),
+        }
+    }
+
+    /// top left corner of a free w x h area
+    fn allocate(&mut self, w: u32, h: u32, page: (u32, u32)) -> Option<(u32, u32)> {
+        match self {
+            Self::Skyline(skyline) => allocate_skyline(skyline, w, h, page),
+            Self::Shelf(shelves) => allocate_shelf(shelves, w, h, page),
+        }
+    }
+}
+
+fn allocate_skyline(
+    skyline: &mut Vec<Segment>,
+    w: u32,
+    h: u32,
+    page: (u32, u32),
+) -> Option<(u32, u32)> {
+    // lowest position, then leftmost
+    let mut best: Option<(usize, u32, u32)> = None;
+    for (i, segment) in skyline.iter().enumerate() {
+        let x = segment.x;
+        if x + w > page.0 {
+            break;
+        }
+        let y = skyline[i..]
+            .iter()
+            .take_while(|s| s.x < x + w)
+            .map(|s| s.y)
+            .max()
+            .unwrap_or(0);
+        if y + h > page.1 {
+            continue;
+        }
+        if best.is_none_or(|(_, bx, by)| (y, x) < (by, bx)) {
+            best = Some((i, x, y));
+        }
+    }
+    let (i, x, y) = best?;
+
+    // the new segment covers the ones below it
+    let end = x + w;
+    let mut j = i;
+    while j < skyline.len() && skyline[j].x < end {
+        let segment_end = skyline[j].x + skyline[j].width;
+        if segment_end > end {
+            skyline[j].width = segment_end - end;
+            skyline[j].x = end;
+            break;
+        }
+        j += 1;
+    }
+    skyline.splice(
+        i..j,
+        [Segment {
+            x,
+            y: y + h,
+            width: w,
+        }],
+    );
+
+    // merge neighbours of the same height
+    let mut k = 0;
+    while k + 1 < skyline.len() {
+        if skyline[k].y == skyline[k + 1].y {
+            skyline[k].width += skyline[k + 1].width;
+            skyline.remove(k + 1);
+        } else {
+            k += 1;
+        }
+    }
+    Some((x, y))
+}
+
+fn allocate_shelf(
+    shelves: &mut Vec<Shelf>,
+    w: u32,
+    h: u32,
+    page: (u32, u32),
+) -> Option<(u32, u32)> {
+    if w > page.0 {
+        return None;
+    }
+    // shelf wasting the least height
+    let best = shelves
+        .iter_mut()
+        .filter(|s| s.height >= h && s.cursor + w <= page.0)
+        .min_by_key(|s| s.height - h);
+    if let Some(shelf) = best {
+        let x = shelf.cursor;
+        shelf.cursor += w;
+        return Some((x, shelf.y));
+    }
+
+    let y = shelves.last().map_or(0, |s| s.y + s.height);
+    if y + h > page.1 {
+        return None;
+    }
+    shelves.push(Shelf {
+        y,
+        height: h,
+        cursor: w,
+    });
+    Some((0, y))
+}
+
+/// handle of an image in an atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtlasHandle(pub usize);
+
+/// placement of an image, without its padding and extrusion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtlasEntry {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// placement of the images of an atlas, computed on the cpu
+///
+/// it can be computed offline, serialized (with the serde feature), and turned back into a TextureAtlas
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtlasLayout {
+    pub page_size: (u32, u32),
+    /// empty texels between images
+    pub padding: u32,
+    /// texels of the image borders repeated around them
+    pub extrusion: u32,
+    pub algorithm: PackingAlgorithm,
+    pub pages: Vec<PageAllocator>,
+    pub entries: Vec<AtlasEntry>,
+}
+
+impl AtlasLayout {
+    pub fn new(page_size: (u32, u32)) -> Self {
+        Self {
+            page_size,
+            padding: 1,
+            extrusion: 1,
+            algorithm: PackingAlgorithm::default(),
+            pages: vec![],
+            entries: vec![],
+        }
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_extrusion(mut self, extrusion: u32) -> Self {
+        self.extrusion = extrusion;
+        self
+    }
+
+    pub fn with_algorithm(mut self, algorithm: PackingAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// find room for a width x height image, in a new page if none has enough
+    pub fn allocate(&mut self, width: u32, height: u32) -> Result<AtlasHandle, AtlasError> {
+        // the padding goes on the right and bottom sides, the page borders count as padding
+        let too_large = AtlasError::TooLarge { width, height };
+        let border = self.extrusion * 2 + self.padding;
+        let w = width.checked_add(border).ok_or(too_large.clone())?;
+        let h = height.checked_add(border).ok_or(too_large.clone())?;
+        let page_area = (
+            self.page_size.0 + self.padding,
+            self.page_size.1 + self.padding,
+        );
+
+        let mut placement = None;
+        for (page, allocator) in self.pages.iter_mut().enumerate() {
+            if let Some(position) = allocator.allocate(w, h, page_area) {
+                placement = Some((page, position));
+                break;
+            }
+        }
+        let (page, (x, y)) = match placement {
+            Some(placement) => placement,
+            None => {
+                let mut allocator = PageAllocator::new(self.algorithm, page_area.0);
+                let position = allocator.allocate(w, h, page_area).ok_or(too_large)?;
+                self.pages.push(allocator);
+                (self.pages.len() - 1, position)
+            }
+        };
+
+        self.entries.push(AtlasEntry {
+            page,
+            x: x + self.extrusion,
+            y: y + self.extrusion,
+            width,
+            height,
+        });
+        Ok(AtlasHandle(self.entries.len() - 1))
+    }
+
+    pub fn entry(&self, handle: AtlasHandle) -> AtlasEntry {
+        self.entries[handle.0]
+    }
+
+    /// normalized texture coordinates of an image in its page
+    pub fn uv_rect(&self, handle: AtlasHandle) -> Rect {
+        let e = self.entry(handle);
+        let (w, h) = (self.page_size.0 as f32, self.page_size.1 as f32);
+        Rect::new(
+            e.x as f32 / w,
+            e.y as f32 / h,
+            e.width as f32 / w,
+            e.height as f32 / h,
+        )
+    }
+
+    /// texel rect of an image in its page
+    pub fn rect(&self, handle: AtlasHandle) -> Rect {
+        let e = self.entry(handle);
+        Rect::new(e.x as f32, e.y as f32, e.width as f32, e.height as f32)
+    }
+}
+
+/// images packed into one or more textures
+#[derive(Debug)]
+pub struct TextureAtlas {
+    pub layout: AtlasLayout,
+    pub pages: Vec<Texture>,
+    format: wgpu::TextureFormat,
+    filter: wgpu::FilterMode,
+}
+
+impl TextureAtlas {
+    /// empty atlas of rgba8 srgb pages
+    pub fn new(layout: AtlasLayout) -> Self {
+        Self::with_format(layout, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    /// empty atlas, images are added as texels of format
+    pub fn with_format(layout: AtlasLayout, format: wgpu::TextureFormat) -> Self {
+        Self {
+            layout,
+            pages: vec![],
+            format,
+            filter: wgpu::FilterMode::Linear,
+        }
+    }
+
+    /// filtering of the pages created from now on
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// upload the images of a precomputed layout, one per entry in handle order
+    pub fn from_layout(
+        layout: AtlasLayout,
+        format: wgpu::TextureFormat,
+        images: &[&[u8]],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, AtlasError> {
+        let mut atlas = Self::with_format(layout, format);
+        atlas.create_pages(device, queue);
+        for i in 0..atlas.layout.entries.len() {
+            let data = images.get(i).ok_or(AtlasError::MissingImage(i))?;
+            atlas.upload(AtlasHandle(i), data, queue)?;
+        }
+        Ok(atlas)
+    }
+
+    /// pack and upload a width x height image of tightly packed texels
+    pub fn add(
+        &mut self,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<AtlasHandle, AtlasError> {
+        self.check_size(width, height, data)?;
+        let handle = self.layout.allocate(width, height)?;
+        self.create_pages(device, queue);
+        self.upload(handle, data, queue)?;
+        Ok(handle)
+    }
+
+    pub fn uv_rect(&self, handle: AtlasHandle) -> Rect {
+        self.layout.uv_rect(handle)
+    }
+
+    /// texture holding an image
+    pub fn page(&self, handle: AtlasHandle) -> &Texture {
+        &self.pages[self.layout.entry(handle).page]
+    }
+
+    fn texel_size(&self) -> usize {
+        self.format.block_size(None).unwrap_or(4) as usize
+    }
+
+    fn check_size(&self, width: u32, height: u32, data: &[u8]) -> Result<(), AtlasError> {
+        let expected = width as usize * height as usize * self.texel_size();
+        if data.len() != expected {
+            return Err(AtlasError::DataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn create_pages(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        while self.pages.len() < self.layout.pages.len() {
+            let page = TextureBuilder::new()
+                .with_format(self.format)
+                .with_min_filter(self.filter)
+                .with_mag_filter(self.filter)
+                .build(self.layout.page_size, device, queue);
+            self.pages.push(page);
+        }
+    }
+
+    /// write the image and its extruded borders
+    fn upload(
+        &self,
+        handle: AtlasHandle,
+        data: &[u8],
+        queue: &wgpu::Queue,
+    ) -> Result<(), AtlasError> {
+        let entry = self.layout.entry(handle);
+        self.check_size(entry.width, entry.height, data)?;
+        if entry.width == 0 || entry.height == 0 {
+            return Ok(());
+        }
+
+        let e = self.layout.extrusion;
+        let (w, h) = (entry.width + 2 * e, entry.height + 2 * e);
+        let extruded = extrude(data, (entry.width, entry.height), e, self.texel_size());
+        self.pages[entry.page].upload_region((entry.x - e, entry.y - e), (w, h), &extruded, queue);
+        Ok(())
+    }
+}
+
+/// copy of a non empty image with its border texels repeated extrusion times around it
+fn extrude(data: &[u8], (width, height): (u32, u32), extrusion: u32, texel: usize) -> Vec<u8> {
+    let (w, h) = (
+        (width + 2 * extrusion) as usize,
+        (height + 2 * extrusion) as usize,
+    );
+    let (width, height, e) = (width as usize, height as usize, extrusion as usize);
+    let mut extruded = vec![0; w * h * texel];
+    for y in 0..h {
+        let src_y = y.saturating_sub(e).min(height - 1);
+        for x in 0..w {
+            let src_x = x.saturating_sub(e).min(width - 1);
+            let src = (src_y * width + src_x) * texel;
+            let dst = (y * w + x) * texel;
+            extruded[dst..dst + texel].copy_from_slice(&data[src..src + texel]);
+        }
+    }
+    extruded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: AtlasEntry, b: AtlasEntry) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.width
+            && b.x < a.x + a.width
+            && a.y < b.y + b.height
+            && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        for algorithm in [PackingAlgorithm::Skyline, PackingAlgorithm::Shelf] {
+            let mut layout = AtlasLayout::new((64, 64)).with_algorithm(algorithm);
+            let handles: Vec<_> = [(10, 12), (20, 8), (7, 7), (30, 30), (16, 5), (9, 20)]
+                .iter()
+                .map(|&(w, h)| layout.allocate(w, h).unwrap())
+                .collect();
+            assert_eq!(layout.pages.len(), 1);
+            for (i, &a) in handles.iter().enumerate() {
+                let a = layout.entry(a);
+                assert!(a.x + a.width <= 64 && a.y + a.height <= 64);
+                for &b in &handles[i + 1..] {
+                    assert!(!overlap(a, layout.entry(b)), "{algorithm:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_page_opens_a_new_one() {
+        let mut layout = AtlasLayout::new((32, 32)).with_padding(0).with_extrusion(0);
+        let first = layout.allocate(32, 32).unwrap();
+        let second = layout.allocate(32, 32).unwrap();
+        assert_eq!(layout.entry(first).page, 0);
+        assert_eq!(layout.entry(second).page, 1);
+        assert_eq!(
+            layout.allocate(33, 1),
+            Err(AtlasError::TooLarge {
+                width: 33,
+                height: 1
+            })
+        );
+        assert_eq!(
+            layout.allocate(u32::MAX, 1),
+            Err(AtlasError::TooLarge {
+                width: u32::MAX,
+                height: 1
+            })
+        );
+    }
+
+    #[test]
+    fn padding_and_extrusion() {
+        let mut layout = AtlasLayout::new((16, 16))
+            .with_padding(2)
+            .with_extrusion(1)
+            .with_algorithm(PackingAlgorithm::Shelf);
+        let a = layout.allocate(4, 4).unwrap();
+        let b = layout.allocate(4, 4).unwrap();
+        let (a, b) = (layout.entry(a), layout.entry(b));
+        assert_eq!((a.x, a.y), (1, 1));
+        // extrusion of a, padding, extrusion of b
+        assert_eq!((b.x, b.y), (a.x + a.width + 1 + 2 + 1, 1));
+        // 16 = 1 + 14 + 1, the page border counts as padding
+        assert!(layout.allocate(14, 14).is_ok());
+        assert!(layout.allocate(15, 14).is_err());
+    }
+
+    #[test]
+    fn extrudes_borders() {
+        let data = [1, 2, 3, 4];
+        let extruded = extrude(&data, (2, 2), 1, 1);
+        #[rustfmt::skip]
+        assert_eq!(extruded, [
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 4, 4,
+            3, 3, 4, 4,
+        ]);
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use wgpu::util::DeviceExt;
+
+use crate::graphics::Texture;
+use crate::layout::GpuUniform;
+
+const BLIT_WGSL: &str = r#"
+struct Blit {
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    // 0 nearest filtering, 1 bilinear
+    linear: u32,
+    // linear source to a display encoded target, the shader encodes the output
+    encode_srgb: u32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> blit: Blit;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// fullscreen triangle over the viewport
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = blit.uv_offset + uv * blit.uv_scale;
+    return out;
+}
+
+fn load(texel: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(texel, vec2<i32>(0), size - 1), 0);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+// the texels are loaded and filtered here, the float formats that can't be sampled work too
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(source));
+    var color: vec4<f32>;
+    if blit.linear == 0u {
+        color = load(vec2<i32>(floor(in.uv * size)));
+    } else {
+        let position = in.uv * size - 0.5;
+        let base = vec2<i32>(floor(position));
+        let t = fract(position);
+        let top = mix(load(base), load(base + vec2<i32>(1, 0)), t.x);
+        let bottom = mix(load(base + vec2<i32>(0, 1)), load(base + vec2<i32>(1, 1)), t.x);
+        color = mix(top, bottom, t.y);
+    }
+
+    if blit.encode_srgb != 0u {
+        color = vec4<f32>(linear_to_srgb(max(color.rgb, vec3<f32>(0.0))), color.a);
+    }
+    return color;
+}
+"#;
+
+/// how the source is scaled to the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlitScaling {
+    /// cover the destination, ignoring the aspect ratio
+    #[default]
+    Stretch,
+    /// largest size keeping the aspect ratio, with bars on the sides
+    Fit,
+    /// largest integer multiple of the source size, centered
+    Integer,
+    /// cover the destination keeping the aspect ratio, the source is cropped
+    Fill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlitMode {
+    pub scaling: BlitScaling,
+    pub filter: wgpu::FilterMode,
+    /// color of the destination around the source
+    pub clear_color: wgpu::Color,
+}
+
+impl Default for BlitMode {
+    fn default() -> Self {
+        Self {
+            scaling: BlitScaling::default(),
+            filter: wgpu::FilterMode::Linear,
+            clear_color: wgpu::Color::BLACK,
+        }
+    }
+}
+
+impl BlitMode {
+    pub fn new(scaling: BlitScaling) -> Self {
+        Self {
+            scaling,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_clear_color(mut self, color: wgpu::Color) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    /// viewport in the destination (x, y, width, height) and uv rect of the source (offset,
+    /// scale) for the given sizes
+    pub fn placement(&self, src: (u32, u32), dst: (u32, u32)) -> ([f32; 4], [f32; 4]) {
+        let (sw, sh) = (src.0.max(1) as f32, src.1.max(1) as f32);
+        let (dw, dh) = (dst.0.max(1) as f32, dst.1.max(1) as f32);
+        let centered = |w: f32, h: f32| [((dw - w) * 0.5).floor(), ((dh - h) * 0.5).floor(), w, h];
+        let full_uv = [0.0, 0.0, 1.0, 1.0];
+
+        match self.scaling {
+            BlitScaling::Stretch => ([0.0, 0.0, dw, dh], full_uv),
+            BlitScaling::Fit => {
+                let scale = (dw / sw).min(dh / sh);
+                (centered(sw * scale, sh * scale), full_uv)
+            }
+            BlitScaling::Integer => {
+                // a source larger than the destination is shown at 1:1, cropped
+                let scale = (dw / sw).min(dh / sh).floor().max(1.0);
+                let (w, h) = (sw * scale, sh * scale);
+                let viewport = centered(w.min(dw), h.min(dh));
+                let uv_scale = [viewport[2] / w, viewport[3] / h];
+                let uv = [(1.0 - uv_scale[0]) * 0.5, (1.0 - uv_scale[1]) * 0.5];
+                (viewport, [uv[0], uv[1], uv_scale[0], uv_scale[1]])
+            }
+            BlitScaling::Fill => {
+                let scale = (dw / sw).max(dh / sh);
+                let uv_scale = [dw / (sw * scale), dh / (sh * scale)];
+                let uv = [(1.0 - uv_scale[0]) * 0.5, (1.0 - uv_scale[1]) * 0.5];
+                ([0.0, 0.0, dw, dh], [uv[0], uv[1], uv_scale[0], uv_scale[1]])
+            }
+        }
+    }
+}
+
+/// unorm formats with an srgb variant hold display encoded values, the others are linear (srgb
+/// formats are decoded when loaded)
+fn display_encoded(format: wgpu::TextureFormat) -> bool {
+    format.add_srgb_suffix() != format
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct BlitUniform {
+    uv_offset: [f32; 2],
+    uv_scale: [f32; 2],
+    linear: u32,
+    encode_srgb: u32,
+}
+
+/// pipelines of Gpu::blit, one per destination format, created on first use
+#[derive(Debug)]
+pub(crate) struct Blitter {
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    pipelines: Mutex<HashMap<wgpu::TextureFormat, Arc<wgpu::RenderPipeline>>>,
+}
+
+impl Blitter {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("blit_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("blit_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blit_shader"),
+            source: wgpu::ShaderSource::Wgsl(BLIT_WGSL.into()),
+        });
+
+        Self {
+            layout,
+            pipeline_layout,
+            shader,
+            pipelines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Arc<wgpu::RenderPipeline> {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                Arc::new(
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some("blit_pipeline"),
+                        layout: Some(&self.pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &self.shader,
+                            entry_point: "vs_main",
+                            buffers: &[],
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &self.shader,
+                            entry_point: "fs_main",
+                            targets: &[Some(format.into())],
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                    }),
+                )
+            })
+            .clone()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn blit(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        src: &Texture,
+        dst_view: &wgpu::TextureView,
+        dst_format: wgpu::TextureFormat,
+        dst_size: (u32, u32),
+        mode: BlitMode,
+    ) {
+        let (viewport, uv) = mode.placement((src.size.width, src.size.height), dst_size);
+        let uniform = BlitUniform {
+            uv_offset: [uv[0], uv[1]],
+            uv_scale: [uv[2], uv[3]],
+            linear: (mode.filter == wgpu::FilterMode::Linear) as u32,
+            encode_srgb: (display_encoded(dst_format) && !display_encoded(src.texture.format()))
+                as u32,
+        };
+        // each blit has its own buffer, several can be recorded in the same encoder
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("blit_buffer"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("blit_bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&src.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline = self.pipeline(device, dst_format);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("blit_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: dst_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(mode.clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&pipeline);
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.set_viewport(viewport[0], viewport[1], viewport[2], viewport[3], 0.0, 1.0);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// texel of the source shown at a destination position, as App maps the cursor
+    fn texel(scaling: BlitScaling, src: (u32, u32), dst: (u32, u32), p: (f32, f32)) -> (u32, u32) {
+        let (viewport, uv) = BlitMode::new(scaling).placement(src, dst);
+        let map = |p: f32, i: usize| {
+            let t = (p - viewport[i]) / viewport[i + 2];
+            ((uv[i] + t * uv[i + 2]) * [src.0, src.1][i] as f32) as u32
+        };
+        (map(p.0, 0), map(p.1, 1))
+    }
+
+    #[test]
+    fn stretch_covers_the_destination() {
+        let placement = BlitMode::new(BlitScaling::Stretch).placement((320, 180), (1280, 1024));
+        assert_eq!(
+            placement,
+            ([0.0, 0.0, 1280.0, 1024.0], [0.0, 0.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn fit_keeps_the_aspect_ratio() {
+        let placement = BlitMode::new(BlitScaling::Fit).placement((100, 50), (300, 300));
+        assert_eq!(placement, ([0.0, 75.0, 300.0, 150.0], [0.0, 0.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn integer_uses_whole_multiples() {
+        let placement = BlitMode::new(BlitScaling::Integer).placement((100, 50), (350, 180));
+        assert_eq!(
+            placement,
+            ([25.0, 15.0, 300.0, 150.0], [0.0, 0.0, 1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn integer_crops_larger_sources() {
+        let placement = BlitMode::new(BlitScaling::Integer).placement((400, 200), (300, 100));
+        assert_eq!(
+            placement,
+            ([0.0, 0.0, 300.0, 100.0], [0.125, 0.25, 0.75, 0.5])
+        );
+    }
+
+    #[test]
+    fn fill_crops_the_source() {
+        let placement = BlitMode::new(BlitScaling::Fill).placement((100, 100), (200, 100));
+        assert_eq!(placement, ([0.0, 0.0, 200.0, 100.0], [0.0, 0.25, 1.0, 0.5]));
+    }
+
+    #[test]
+    fn positions_map_back_to_texels() {
+        assert_eq!(
+            texel(BlitScaling::Stretch, (320, 180), (1280, 720), (6.0, 719.0)),
+            (1, 179)
+        );
+        assert_eq!(
+            texel(BlitScaling::Fit, (100, 50), (300, 300), (4.5, 76.5)),
+            (1, 0)
+        );
+        assert_eq!(
+            texel(BlitScaling::Fit, (100, 50), (300, 300), (150.0, 150.0)),
+            (50, 25)
+        );
+        assert_eq!(
+            texel(BlitScaling::Integer, (100, 50), (350, 180), (26.0, 164.0)),
+            (0, 49)
+        );
+        assert_eq!(
+            texel(BlitScaling::Integer, (400, 200), (300, 100), (0.5, 0.5)),
+            (50, 50)
+        );
+        assert_eq!(
+            texel(BlitScaling::Fill, (100, 100), (200, 100), (1.0, 0.5)),
+            (0, 25)
+        );
+        assert_eq!(
+            texel(BlitScaling::Fill, (100, 100), (200, 100), (199.0, 99.5)),
+            (99, 74)
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use std::time::Duration;
+
+use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::gpu::Gpu;
+use crate::layout::GpuUniform;
+
+/// camera data as seen by the shaders
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+pub struct CameraUniform {
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub view_proj: [[f32; 4]; 4],
+    /// world position of the camera (w = 1)
+    pub eye: [f32; 4],
+}
+
+pub trait Camera {
+    fn eye(&self) -> Vec3;
+
+    fn target(&self) -> Vec3;
+
+    fn up(&self) -> Vec3;
+
+    /// move the camera
+    fn look_at(&mut self, eye: Vec3, target: Vec3);
+
+    /// set the width / height ratio of the projection
+    fn set_aspect(&mut self, aspect: f32);
+
+    fn projection(&self) -> Mat4;
+
+    fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye(), self.target(), self.up())
+    }
+
+    fn uniform(&self) -> CameraUniform {
+        let view = self.view();
+        let proj = self.projection();
+        CameraUniform {
+            view: view.to_cols_array_2d(),
+            proj: proj.to_cols_array_2d(),
+            view_proj: (proj * view).to_cols_array_2d(),
+            eye: self.eye().extend(1.0).to_array(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PerspectiveCamera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// vertical field of view in radians
+    pub fovy: f32,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl PerspectiveCamera {
+    pub fn new(eye: Vec3, target: Vec3) -> Self {
+        Self {
+            eye,
+            target,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PerspectiveCamera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.0, 5.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fovy: 45f32.to_radians(),
+            aspect: 1.0,
+            znear: 0.1,
+            zfar: 1000.0,
+        }
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn eye(&self) -> Vec3 {
+        self.eye
+    }
+
+    fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    fn up(&self) -> Vec3 {
+        self.up
+    }
+
+    fn look_at(&mut self, eye: Vec3, target: Vec3) {
+        self.eye = eye;
+        self.target = target;
+    }
+
+    fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+
+    fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrthographicCamera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// height of the visible area, the width is deduced from the aspect
+    pub height: f32,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl OrthographicCamera {
+    pub fn new(eye: Vec3, target: Vec3, height: f32) -> Self {
+        Self {
+            eye,
+            target,
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// camera looking down -Z, suited for 2D scenes
+    pub fn new_2d(center: Vec2, height: f32) -> Self {
+        Self {
+            eye: center.extend(1.0),
+            target: center.extend(0.0),
+            height,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for OrthographicCamera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.0, 1.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            height: 2.0,
+            aspect: 1.0,
+            znear: -1000.0,
+            zfar: 1000.0,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn eye(&self) -> Vec3 {
+        self.eye
+    }
+
+    fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    fn up(&self) -> Vec3 {
+        self.up
+    }
+
+    fn look_at(&mut self, eye: Vec3, target: Vec3) {
+        self.eye = eye;
+        self.target = target;
+    }
+
+    fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+
+    fn projection(&self) -> Mat4 {
+        let half_h = self.height * 0.5;
+        let half_w = half_h * self.aspect;
+        Mat4::orthographic_rh(-half_w, half_w, -half_h, half_h, self.znear, self.zfar)
+    }
+}
+
+/// a camera with its uniform buffer and bind group
+/// the bind group layout has the uniform at binding 0, visible by all stages
+pub struct GpuCamera<C> {
+    pub camera: C,
+    pub buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl<C: Camera> GpuCamera<C> {
+    pub fn new(gpu: &Gpu, mut camera: C) -> Self {
+        camera.set_aspect(gpu.aspect_ratio());
+
+        let buffer = gpu
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("camera_buffer"),
+                contents: bytemuck::bytes_of(&camera.uniform()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("camera_bind_group_layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT
+                            | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                });
+
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("camera_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            camera,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// sync the aspect ratio with the scene target and upload the uniform
+    pub fn update(&mut self, gpu: &Gpu) {
+        self.camera.set_aspect(gpu.aspect_ratio());
+        gpu.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.camera.uniform()));
+    }
+}
+
+/// move a camera from the window events
+pub trait CameraController<C: Camera> {
+    /// handle a window event, return true if the event has been consumed
+    fn events(&mut self, event: &WindowEvent) -> bool;
+
+    /// apply the accumulated input to the camera
+    fn update(&mut self, camera: &mut C, dt: Duration);
+}
+
+/// mouse state shared by the controllers
+#[derive(Debug, Default, Clone, Copy)]
+struct MouseState {
+    position: Option<Vec2>,
+    /// movement since the last update
+    delta: Vec2,
+    /// scroll since the last update, in lines
+    scroll: f32,
+    left: bool,
+    right: bool,
+    middle: bool,
+}
+
+impl MouseState {
+    fn events(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vec2::new(position.x as f32, position.y as f32);
+                if let Some(last) = self.position {
+                    self.delta += position - last;
+                }
+                self.position = Some(position);
+                self.left || self.right || self.middle
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.position = None;
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.left = pressed,
+                    MouseButton::Right => self.right = pressed,
+                    MouseButton::Middle => self.middle = pressed,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// take the accumulated movement and scroll
+    fn take(&mut self) -> (Vec2, f32) {
+        let taken = (self.delta, self.scroll);
+        self.delta = Vec2::ZERO;
+        self.scroll = 0.0;
+        taken
+    }
+}
+
+/// rotate around the target with the left button, pan with the right or
+/// middle button and zoom with the wheel
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitController {
+    /// radians per pixel
+    pub rotate_speed: f32,
+    /// fraction of the distance per pixel
+    pub pan_speed: f32,
+    /// fraction of the distance per scroll line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    mouse: MouseState,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            rotate_speed: 0.005,
+            pan_speed: 0.002,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl<C: Camera> CameraController<C> for OrbitController {
+    fn events(&mut self, event: &WindowEvent) -> bool {
+        self.mouse.events(event)
+    }
+
+    fn update(&mut self, camera: &mut C, _dt: Duration) {
+        let (delta, scroll) = self.mouse.take();
+        let (eye, mut target, up) = (camera.eye(), camera.target(), camera.up());
+
+        let offset = eye - target;
+        let mut distance = offset.length();
+        let mut yaw = offset.x.atan2(offset.z);
+        let mut pitch = (offset.y / distance.max(f32::EPSILON))
+            .clamp(-1.0, 1.0)
+            .asin();
+
+        if self.mouse.left {
+            yaw -= delta.x * self.rotate_speed;
+            pitch += delta.y * self.rotate_speed;
+            pitch = pitch.clamp(-1.55, 1.55);
+        }
+
+        if self.mouse.right || self.mouse.middle {
+            let forward = (target - eye).normalize_or_zero();
+            let right = forward.cross(up).normalize_or_zero();
+            let cam_up = right.cross(forward);
+            target += (-right * delta.x + cam_up * delta.y) * self.pan_speed * distance;
+        }
+
+        distance = (distance * (1.0 - scroll * self.zoom_speed))
+            .clamp(self.min_distance, self.max_distance);
+
+        let offset = Vec3::new(
+            pitch.cos() * yaw.sin(),
+            pitch.sin(),
+            pitch.cos() * yaw.cos(),
+        ) * distance;
+        camera.look_at(target + offset, target);
+    }
+}
+
+/// WASD to move, Q/E or Space/LShift to go down/up, hold the right button
+/// to look around
+#[derive(Debug, Clone, Copy)]
+pub struct FlyController {
+    /// units per second
+    pub speed: f32,
+    /// radians per pixel
+    pub sensitivity: f32,
+    /// speed multiplier while LControl is held
+    pub boost: f32,
+    forward: f32,
+    backward: f32,
+    left: f32,
+    right: f32,
+    up: f32,
+    down: f32,
+    boosted: bool,
+    mouse: MouseState,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {
+            speed: 5.0,
+            sensitivity: 0.003,
+            boost: 4.0,
+            forward: 0.0,
+            backward: 0.0,
+            left: 0.0,
+            right: 0.0,
+            up: 0.0,
+            down: 0.0,
+            boosted: false,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl<C: Camera> CameraController<C> for FlyController {
+    fn events(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    virtual_keycode: Some(key),
+                    state,
+                    ..
+                },
+            ..
+        } = event
+        {
+            let amount = if *state == ElementState::Pressed {
+                1.0
+            } else {
+                0.0
+            };
+            match key {
+                VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = amount,
+                VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = amount,
+                VirtualKeyCode::A | VirtualKeyCode::Left => self.left = amount,
+                VirtualKeyCode::D | VirtualKeyCode::Right => self.right = amount,
+                VirtualKeyCode::E | VirtualKeyCode::Space => self.up = amount,
+                VirtualKeyCode::Q | VirtualKeyCode::LShift => self.down = amount,
+                VirtualKeyCode::LControl => self.boosted = amount > 0.0,
+                _ => return false,
+            }
+            return true;
+        }
+        self.mouse.events(event)
+    }
+
+    fn update(&mut self, camera: &mut C, dt: Duration) {
+        let (delta, _) = self.mouse.take();
+        let (eye, target, up) = (camera.eye(), camera.target(), camera.up());
+
+        let mut forward = (target - eye).normalize_or_zero();
+        if self.mouse.right {
+            let mut yaw = forward.x.atan2(-forward.z);
+            let mut pitch = forward.y.clamp(-1.0, 1.0).asin();
+            yaw += delta.x * self.sensitivity;
+            pitch = (pitch - delta.y * self.sensitivity).clamp(-1.55, 1.55);
+            forward = Vec3::new(
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                -pitch.cos() * yaw.cos(),
+            );
+        }
+
+        let right = forward.cross(up).normalize_or_zero();
+        let direction = forward * (self.forward - self.backward)
+            + right * (self.right - self.left)
+            + up * (self.up - self.down);
+        let speed = if self.boosted {
+            self.speed * self.boost
+        } else {
+            self.speed
+        };
+        let eye = eye + direction.normalize_or_zero() * speed * dt.as_secs_f32();
+        camera.look_at(eye, eye + forward);
+    }
+}
+
+/// 2D controller for orthographic cameras: drag with the left, right or
+/// middle button to pan, wheel to zoom around the cursor
+#[derive(Debug, Clone, Copy)]
+pub struct PanZoomController {
+    /// fraction of the height per scroll line
+    pub zoom_speed: f32,
+    pub min_height: f32,
+    pub max_height: f32,
+    /// size of the window in pixels, updated on resize
+    window_width: f32,
+    window_height: f32,
+    mouse: MouseState,
+}
+
+impl PanZoomController {
+    pub fn new(gpu: &Gpu) -> Self {
+        let (width, height) = gpu.scene_size();
+        Self {
+            window_width: width.max(1) as f32,
+            window_height: height.max(1) as f32,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PanZoomController {
+    fn default() -> Self {
+        Self {
+            zoom_speed: 0.1,
+            min_height: 0.01,
+            max_height: 10000.0,
+            window_width: 1.0,
+            window_height: 1.0,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl CameraController<OrthographicCamera> for PanZoomController {
+    fn events(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::Resized(size) = event {
+            self.window_width = size.width.max(1) as f32;
+            self.window_height = size.height.max(1) as f32;
+            return false;
+        }
+        self.mouse.events(event)
+    }
+
+    fn update(&mut self, camera: &mut OrthographicCamera, _dt: Duration) {
+        let (delta, scroll) = self.mouse.take();
+        let up = camera.up.normalize_or_zero();
+        let right = (camera.target - camera.eye).cross(up).normalize_or_zero();
+
+        // world units per pixel
+        let scale = camera.height / self.window_height;
+        let mut pan = Vec3::ZERO;
+        if self.mouse.left || self.mouse.right || self.mouse.middle {
+            pan += -right * delta.x * scale + up * delta.y * scale;
+        }
+
+        if scroll != 0.0 {
+            let height = (camera.height * (1.0 - scroll * self.zoom_speed))
+                .clamp(self.min_height, self.max_height);
+            // keep the point under the cursor fixed
+            if let Some(cursor) = self.mouse.position {
+                let from_center = cursor - Vec2::new(self.window_width, self.window_height) * 0.5;
+                let new_scale = height / self.window_height;
+                pan += (right * from_center.x - up * from_center.y) * (scale - new_scale);
+            }
+            camera.height = height;
+        }
+
+        camera.eye += pan;
+        camera.target += pan;
+    }
+}
@@ -0,0 +1,409 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use glam::{Mat4, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::gpu::Gpu;
+use crate::layout::{GpuUniform, VertexLayout};
+use crate::mesh::Aabb;
+
+const DEBUG_DRAW_WGSL: &str = r#"
+struct Globals {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+
+struct VertexInput {
+    @location(0) pos: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(in.pos, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+/// segments used for circles and spheres
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct DebugVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct DebugGlobals {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DebugLine {
+    a: Vec3,
+    b: Vec3,
+    color: [f32; 4],
+    /// time left before the line disappears, drawn for a single frame when None
+    remaining: Option<Duration>,
+}
+
+/// immediate mode lines drawn over the scene, queued during update
+///
+/// shapes last one frame unless queued inside with_duration, prepare uploads them and render records them
+#[derive(Debug)]
+pub struct DebugDraw {
+    /// hide the lines behind the scene, needs a depth format
+    pub depth_test: bool,
+    /// skip prepare and render
+    pub enabled: bool,
+    lines: Vec<DebugLine>,
+    duration: Option<Duration>,
+    pipeline: wgpu::RenderPipeline,
+    /// pipeline ignoring the depth buffer, when there is one
+    overlay_pipeline: Option<wgpu::RenderPipeline>,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    vertex_capacity: u64,
+    vertex_count: u32,
+}
+
+impl DebugDraw {
+    /// debug lines drawn to the scene target without depth testing
+    pub fn new(gpu: &Gpu) -> Self {
+        Self::with_formats(gpu, gpu.get_scene_format(), None)
+    }
+
+    /// debug lines drawn to the scene target, in a pass with a depth attachment of depth_format
+    pub fn with_depth(gpu: &Gpu, depth_format: wgpu::TextureFormat) -> Self {
+        Self::with_formats(gpu, gpu.get_scene_format(), Some(depth_format))
+    }
+
+    pub fn with_formats(
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let device = &gpu.device;
+
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_draw_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug_draw_globals_buffer"),
+            contents: bytemuck::bytes_of(&DebugGlobals {
+                view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug_draw_bind_group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("debug_draw_shader"),
+            source: wgpu::ShaderSource::Wgsl(DEBUG_DRAW_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_draw_pipeline_layout"),
+            bind_group_layouts: &[&globals_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |depth_compare: wgpu::CompareFunction| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("debug_draw_pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[DebugVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled: false,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline(wgpu::CompareFunction::LessEqual);
+        let overlay_pipeline = depth_format.map(|_| create_pipeline(wgpu::CompareFunction::Always));
+
+        let vertex_capacity = 1024;
+        Self {
+            depth_test: depth_format.is_some(),
+            enabled: true,
+            lines: vec![],
+            duration: None,
+            pipeline,
+            overlay_pipeline,
+            globals_buffer,
+            globals_bind_group,
+            vertex_buffer: Self::create_vertex_buffer(device, vertex_capacity),
+            vertex_capacity,
+            vertex_count: 0,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_draw_vertex_buffer"),
+            size: capacity * std::mem::size_of::<DebugVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// keep the shapes queued by f on screen for duration
+    pub fn with_duration(&mut self, duration: Duration, f: impl FnOnce(&mut Self)) {
+        let previous = self.duration.replace(duration);
+        f(self);
+        self.duration = previous;
+    }
+
+    pub fn line(&mut self, a: impl Into<Vec3>, b: impl Into<Vec3>, color: [f32; 4]) {
+        self.lines.push(DebugLine {
+            a: a.into(),
+            b: b.into(),
+            color,
+            remaining: self.duration,
+        });
+    }
+
+    /// segment from origin to origin + direction
+    pub fn ray(&mut self, origin: impl Into<Vec3>, direction: impl Into<Vec3>, color: [f32; 4]) {
+        let origin = origin.into();
+        self.line(origin, origin + direction.into(), color);
+    }
+
+    /// line from a to b with a head at b
+    pub fn arrow(&mut self, a: impl Into<Vec3>, b: impl Into<Vec3>, color: [f32; 4]) {
+        let (a, b) = (a.into(), b.into());
+        self.line(a, b, color);
+
+        let dir = b - a;
+        let length = dir.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let dir = dir / length;
+        let (u, v) = dir.any_orthonormal_pair();
+        let head = length * 0.15;
+        for side in [u, -u, v, -v] {
+            self.line(b, b - dir * head + side * head * 0.4, color);
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+        let (min, max) = (aabb.min, aabb.max);
+        let corner = |i: usize| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        // corners differing by one bit share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// circle around normal
+    pub fn circle(
+        &mut self,
+        center: impl Into<Vec3>,
+        normal: impl Into<Vec3>,
+        radius: f32,
+        color: [f32; 4],
+    ) {
+        let center = center.into();
+        let (u, v) = normal.into().normalize_or_zero().any_orthonormal_pair();
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// three circles around the axes
+    pub fn sphere(&mut self, center: impl Into<Vec3>, radius: f32, color: [f32; 4]) {
+        let center = center.into();
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    /// edges of the volume seen by a view projection matrix
+    pub fn frustum(&mut self, view_proj: Mat4, color: [f32; 4]) {
+        let inverse = view_proj.inverse();
+        // wgpu clip space has z in [0, 1]
+        let corner = |i: usize| {
+            let ndc = Vec4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let p = inverse * ndc;
+            p.truncate() / p.w
+        };
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// x, y and z axes of a transform in red, green and blue
+    pub fn axes(&mut self, transform: Mat4, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+        let axes = [
+            (Vec3::X, [1.0, 0.2, 0.2, 1.0]),
+            (Vec3::Y, [0.2, 1.0, 0.2, 1.0]),
+            (Vec3::Z, [0.2, 0.4, 1.0, 1.0]),
+        ];
+        for (axis, color) in axes {
+            self.arrow(origin, transform.transform_point3(axis * size), color);
+        }
+    }
+
+    /// square grid on the xz plane
+    pub fn grid(&mut self, center: impl Into<Vec3>, size: f32, divisions: u32, color: [f32; 4]) {
+        let center = center.into();
+        let half = size * 0.5;
+        let divisions = divisions.max(1);
+        for i in 0..=divisions {
+            let t = -half + size * i as f32 / divisions as f32;
+            self.line(
+                center + Vec3::new(t, 0.0, -half),
+                center + Vec3::new(t, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half, 0.0, t),
+                center + Vec3::new(half, 0.0, t),
+                color,
+            );
+        }
+    }
+
+    /// upload the queued lines and the camera, then age the timed ones by dt
+    pub fn prepare(&mut self, gpu: &Gpu, view_proj: Mat4, dt: Duration) {
+        if !self.enabled {
+            self.lines.clear();
+            self.vertex_count = 0;
+            return;
+        }
+
+        let globals = DebugGlobals {
+            view_proj: view_proj.to_cols_array_2d(),
+        };
+        gpu.queue
+            .write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        let vertices: Vec<DebugVertex> = self
+            .lines
+            .iter()
+            .flat_map(|line| {
+                [
+                    DebugVertex {
+                        pos: line.a.to_array(),
+                        color: line.color,
+                    },
+                    DebugVertex {
+                        pos: line.b.to_array(),
+                        color: line.color,
+                    },
+                ]
+            })
+            .collect();
+
+        if vertices.len() as u64 > self.vertex_capacity {
+            self.vertex_capacity = (vertices.len() as u64).next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(&gpu.device, self.vertex_capacity);
+        }
+        gpu.queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.vertex_count = vertices.len() as u32;
+
+        self.lines.retain_mut(|line| match &mut line.remaining {
+            Some(remaining) if *remaining > dt => {
+                *remaining -= dt;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// record the lines of the last prepare in a single draw call
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if !self.enabled || self.vertex_count == 0 {
+            return;
+        }
+        let pipeline = match &self.overlay_pipeline {
+            Some(overlay) if !self.depth_test => overlay,
+            _ => &self.pipeline,
+        };
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, &self.globals_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..self.vertex_count, 0..1);
+    }
+}
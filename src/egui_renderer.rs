@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use egui::epaint::Shadow;
 use egui::{Context, Visuals};
 use egui_wgpu::renderer::ScreenDescriptor;
@@ -56,6 +58,7 @@ impl EguiRenderer {
         let _ = self.state.on_window_event(&self.context, event);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &mut self,
         device: &Device,
@@ -80,13 +83,13 @@ impl EguiRenderer {
             .tessellate(full_output.shapes, full_output.pixels_per_point);
         for (id, image_delta) in &full_output.textures_delta.set {
             self.renderer
-                .update_texture(&device, &queue, *id, &image_delta);
+                .update_texture(device, queue, *id, image_delta);
         }
         self.renderer
-            .update_buffers(&device, &queue, encoder, &tris, &screen_descriptor);
+            .update_buffers(device, queue, encoder, &tris, &screen_descriptor);
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &window_surface_view,
+                view: window_surface_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
@@ -105,3 +108,42 @@ impl EguiRenderer {
         }
     }
 }
+
+/// short lived messages displayed in the bottom right corner of the window
+pub struct Toasts {
+    toasts: Vec<(String, Instant)>,
+    duration: Duration,
+}
+
+impl Toasts {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            toasts: vec![],
+            duration,
+        }
+    }
+
+    pub fn push(&mut self, message: String) {
+        self.toasts.push((message, Instant::now()));
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        self.toasts
+            .retain(|(_, created)| now - *created < self.duration);
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+            .show(ctx, |ui| {
+                for (message, _) in &self.toasts {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(400.0);
+                        ui.colored_label(egui::Color32::LIGHT_RED, message);
+                    });
+                }
+            });
+    }
+}
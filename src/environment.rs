@@ -0,0 +1,1259 @@
+use std::fmt;
+use std::path::Path;
+
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraUniform;
+use crate::gpu::Gpu;
+use crate::graphics::{Texture, TextureBuilder};
+use crate::layout::GpuUniform;
+
+/// WGSL library of the image based lighting, follows LIGHTING_WGSL
+///
+/// the maps are bound to the lights group, as done by LightBuffer::with_environment
+pub const IBL_WGSL: &str = r#"
+struct Environment {
+    intensity: f32,
+    max_lod: f32,
+}
+
+@group(1) @binding(4) var irradiance_map: texture_cube<f32>;
+@group(1) @binding(5) var prefiltered_map: texture_cube<f32>;
+@group(1) @binding(6) var brdf_lut: texture_2d<f32>;
+@group(1) @binding(7) var environment_sampler: sampler;
+@group(1) @binding(8) var<uniform> environment: Environment;
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let f90 = max(vec3<f32>(1.0 - roughness), f0);
+    return f0 + (f90 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// light coming from the environment, split sum approximation for the specular part
+fn ambient_light(
+    ambient: vec3<f32>,
+    n: vec3<f32>,
+    v: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let kd = (1.0 - f) * (1.0 - metallic);
+
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, n, 0.0).rgb;
+    let r = reflect(-v, n);
+    let lod = roughness * environment.max_lod;
+    let prefiltered = textureSampleLevel(prefiltered_map, environment_sampler, r, lod).rgb;
+    let brdf = textureSampleLevel(brdf_lut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+
+    let diffuse = kd * irradiance * base_color;
+    let specular = prefiltered * (f * brdf.x + brdf.y);
+    return (diffuse + specular) * environment.intensity;
+}
+"#;
+
+/// direction of a texel of a cubemap face, uv in [0, 1] with v down
+const CUBE_WGSL: &str = r#"
+const PI: f32 = 3.14159265359;
+
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let p = uv * 2.0 - 1.0;
+    var dir: vec3<f32>;
+    switch face {
+        case 0u: { dir = vec3<f32>(1.0, -p.y, -p.x); }
+        case 1u: { dir = vec3<f32>(-1.0, -p.y, p.x); }
+        case 2u: { dir = vec3<f32>(p.x, 1.0, p.y); }
+        case 3u: { dir = vec3<f32>(p.x, -1.0, -p.y); }
+        case 4u: { dir = vec3<f32>(p.x, -p.y, 1.0); }
+        default: { dir = vec3<f32>(-p.x, -p.y, -1.0); }
+    }
+    return normalize(dir);
+}
+
+fn radical_inverse(bits_in: u32) -> f32 {
+    var bits = (bits_in << 16u) | (bits_in >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), radical_inverse(i));
+}
+
+// half vector around n distributed with the GGX lobe
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+"#;
+
+const EQUIRECT_WGSL: &str = r#"
+@group(0) @binding(0) var equirect: texture_2d<f32>;
+@group(0) @binding(1) var output: texture_storage_2d_array<rgba16float, write>;
+
+fn load(coords: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    // wraps around horizontally
+    let x = (coords.x % size.x + size.x) % size.x;
+    let y = clamp(coords.y, 0, size.y - 1);
+    return textureLoad(equirect, vec2<i32>(x, y), 0).rgb;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let dir = cube_direction(id.z, (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size));
+    let uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+
+    // bilinear filtering, float32 textures are not filterable
+    let src_size = vec2<i32>(textureDimensions(equirect));
+    let p = uv * vec2<f32>(src_size) - 0.5;
+    let base = vec2<i32>(floor(p));
+    let t = fract(p);
+    let top = mix(load(base, src_size), load(base + vec2<i32>(1, 0), src_size), t.x);
+    let bottom = mix(load(base + vec2<i32>(0, 1), src_size), load(base + vec2<i32>(1, 1), src_size), t.x);
+    textureStore(output, id.xy, id.z, vec4<f32>(mix(top, bottom, t.y), 1.0));
+}
+"#;
+
+const DOWNSAMPLE_WGSL: &str = r#"
+@group(0) @binding(0) var source: texture_2d_array<f32>;
+@group(0) @binding(1) var output: texture_storage_2d_array<rgba16float, write>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(output)) {
+        return;
+    }
+    let p = vec2<i32>(id.xy) * 2;
+    let layer = i32(id.z);
+    let color = textureLoad(source, p, layer, 0)
+        + textureLoad(source, p + vec2<i32>(1, 0), layer, 0)
+        + textureLoad(source, p + vec2<i32>(0, 1), layer, 0)
+        + textureLoad(source, p + vec2<i32>(1, 1), layer, 0);
+    textureStore(output, id.xy, id.z, color * 0.25);
+}
+"#;
+
+const IRRADIANCE_WGSL: &str = r#"
+@group(0) @binding(0) var environment_map: texture_cube<f32>;
+@group(0) @binding(1) var environment_sampler: sampler;
+@group(0) @binding(2) var output: texture_storage_2d_array<rgba16float, write>;
+
+const STEP: f32 = 0.025;
+
+// cosine weighted integral of the radiance over the hemisphere of each direction
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let n = cube_direction(id.z, (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size));
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(n.y) < 0.999);
+    let right = normalize(cross(up, n));
+    let forward = cross(n, right);
+
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+            let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let dir = tangent.x * right + tangent.y * forward + tangent.z * n;
+            // a coarse mip avoids the aliasing of the bright spots
+            let radiance = textureSampleLevel(environment_map, environment_sampler, dir, 2.0).rgb;
+            irradiance += radiance * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    textureStore(output, id.xy, id.z, vec4<f32>(PI * irradiance / count, 1.0));
+}
+"#;
+
+const PREFILTER_WGSL: &str = r#"
+struct Prefilter {
+    roughness: f32,
+    source_size: f32,
+}
+
+@group(0) @binding(0) var environment_map: texture_cube<f32>;
+@group(0) @binding(1) var environment_sampler: sampler;
+@group(0) @binding(2) var output: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3) var<uniform> prefilter: Prefilter;
+
+const SAMPLE_COUNT: u32 = 512u;
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// radiance convolved with the GGX lobe, assuming the view along the normal
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let n = cube_direction(id.z, (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size));
+    let v = n;
+    let roughness = prefilter.roughness;
+    if roughness == 0.0 {
+        let color = textureSampleLevel(environment_map, environment_sampler, n, 0.0).rgb;
+        textureStore(output, id.xy, id.z, vec4<f32>(color, 1.0));
+        return;
+    }
+
+    let texel_solid_angle = 4.0 * PI / (6.0 * prefilter.source_size * prefilter.source_size);
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // sample a mip matching the solid angle of the sample to avoid the fireflies
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * 0.25 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(SAMPLE_COUNT) * pdf + 0.0001);
+            let lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+            color += textureSampleLevel(environment_map, environment_sampler, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    textureStore(output, id.xy, id.z, vec4<f32>(color / weight, 1.0));
+}
+"#;
+
+const BRDF_WGSL: &str = r#"
+@group(0) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+
+const SAMPLE_COUNT: u32 = 1024u;
+
+fn geometry_schlick(n_dot_x: f32, k: f32) -> f32 {
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// scale and bias applied to f0 by the specular integral, indexed by (n.v, roughness)
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size);
+    let n_dot_v = uv.x;
+    let roughness = uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+    let k = roughness * roughness / 2.0;
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if n_dot_l > 0.0 {
+            let g = geometry_schlick(n_dot_v, k) * geometry_schlick(n_dot_l, k);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    let count = f32(SAMPLE_COUNT);
+    textureStore(output, id.xy, vec4<f32>(scale / count, bias / count, 0.0, 1.0));
+}
+"#;
+
+const SKYBOX_WGSL: &str = r#"
+struct Skybox {
+    inv_view_proj: mat4x4<f32>,
+    lod: f32,
+    intensity: f32,
+}
+
+@group(0) @binding(0) var<uniform> skybox: Skybox;
+@group(0) @binding(1) var environment_map: texture_cube<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// fullscreen triangle on the far plane
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let p = skybox.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let dir = normalize(p.xyz / p.w);
+    let color = textureSampleLevel(environment_map, environment_sampler, dir, skybox.lod).rgb;
+    return vec4<f32>(color * skybox.intensity, 1.0);
+}
+"#;
+
+#[derive(Debug)]
+pub enum HdrError {
+    Io(std::io::Error),
+    /// the file is not a radiance rgbe image
+    Format(String),
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdrError::Io(e) => write!(f, "failed to read the hdr image: {e}"),
+            HdrError::Format(e) => write!(f, "invalid hdr image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HdrError {}
+
+impl From<std::io::Error> for HdrError {
+    fn from(e: std::io::Error) -> Self {
+        HdrError::Io(e)
+    }
+}
+
+/// floating point rgba image read from a radiance `.hdr` file
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// rgba texels, row by row from the top
+    pub data: Vec<f32>,
+}
+
+impl HdrImage {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, HdrError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HdrError> {
+        let format_err = |msg: &str| HdrError::Format(msg.to_owned());
+
+        // header lines, ended by an empty line, then the resolution line
+        let mut pos = 0;
+        let mut next_line = || -> Result<&str, HdrError> {
+            let end = bytes[pos..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| format_err("truncated header"))?;
+            let line = std::str::from_utf8(&bytes[pos..pos + end])
+                .map_err(|_| format_err("header is not utf8"))?;
+            pos += end + 1;
+            Ok(line.trim_end())
+        };
+
+        let magic = next_line()?;
+        if !magic.starts_with("#?") {
+            return Err(format_err("missing #? signature"));
+        }
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(HdrError::Format(format!("unsupported format {format}")));
+                }
+            }
+        }
+        let resolution: Vec<&str> = next_line()?.split_whitespace().collect();
+        let (height, width) = match resolution[..] {
+            ["-Y", h, "+X", w] => (
+                h.parse::<u32>().map_err(|_| format_err("invalid height"))?,
+                w.parse::<u32>().map_err(|_| format_err("invalid width"))?,
+            ),
+            _ => return Err(format_err("only -Y h +X w orientations are supported")),
+        };
+
+        // a run of the rle encoding stores up to 127 texels of a channel in 2 bytes
+        let mut rest = &bytes[pos..];
+        let texels = (width as usize)
+            .checked_mul(height as usize)
+            .filter(|&texels| texels > 0)
+            .ok_or_else(|| format_err("invalid image size"))?;
+        if texels / 127 > rest.len() / 8 {
+            return Err(format_err("the image size exceeds the data"));
+        }
+        let len = texels
+            .checked_mul(4)
+            .ok_or_else(|| format_err("invalid image size"))?;
+
+        let mut data = Vec::with_capacity(len);
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for _ in 0..height {
+            rest =
+                read_scanline(rest, &mut scanline).ok_or_else(|| format_err("truncated data"))?;
+            data.extend(scanline.iter().flat_map(|&rgbe| rgbe_to_rgba(rgbe)));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// image of half the size, each texel averages a 2x2 block
+    pub fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let texel = |x: u32, y: u32| {
+            let (x, y) = (x.min(self.width - 1), y.min(self.height - 1));
+            let i = (y as usize * self.width as usize + x as usize) * 4;
+            &self.data[i..i + 4]
+        };
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let block = [
+                    texel(2 * x, 2 * y),
+                    texel(2 * x + 1, 2 * y),
+                    texel(2 * x, 2 * y + 1),
+                    texel(2 * x + 1, 2 * y + 1),
+                ];
+                data.extend((0..4).map(|c| block.iter().map(|t| t[c]).sum::<f32>() * 0.25));
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// read one scanline, flat or run length encoded, and return the remaining bytes
+fn read_scanline<'a>(bytes: &'a [u8], scanline: &mut [[u8; 4]]) -> Option<&'a [u8]> {
+    let width = scanline.len();
+    let encoded = (8..0x8000).contains(&width)
+        && bytes.len() >= 4
+        && bytes[0] == 2
+        && bytes[1] == 2
+        && ((bytes[2] as usize) << 8 | bytes[3] as usize) == width;
+    if !encoded {
+        let len = width * 4;
+        let flat = bytes.get(..len)?;
+        for (texel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            texel.copy_from_slice(rgbe);
+        }
+        return Some(&bytes[len..]);
+    }
+
+    // each channel is encoded separately as runs and literals
+    let mut pos = 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(pos)? as usize;
+            pos += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *bytes.get(pos)?;
+                pos += 1;
+                for texel in scanline.get_mut(x..x + count)? {
+                    texel[channel] = value;
+                }
+                x += count;
+            } else {
+                let values = bytes.get(pos..pos + count)?;
+                pos += count;
+                for (texel, &value) in scanline.get_mut(x..x + count)?.iter_mut().zip(values) {
+                    texel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Some(&bytes[pos..])
+}
+
+fn rgbe_to_rgba([r, g, b, e]: [u8; 4]) -> [f32; 4] {
+    if e == 0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    [
+        (r as f32 + 0.5) * scale,
+        (g as f32 + 0.5) * scale,
+        (b as f32 + 0.5) * scale,
+        1.0,
+    ]
+}
+
+/// sizes of the maps computed from an environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvironmentConfig {
+    /// face size of the environment cubemap
+    pub size: u32,
+    pub irradiance_size: u32,
+    /// face size of the first mip of the prefiltered map
+    pub prefiltered_size: u32,
+    /// mips of the prefiltered map, from roughness 0 to 1
+    pub prefiltered_mips: u32,
+    pub brdf_lut_size: u32,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            prefiltered_mips: 5,
+            brdf_lut_size: 256,
+        }
+    }
+}
+
+impl EnvironmentConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_irradiance_size(mut self, size: u32) -> Self {
+        self.irradiance_size = size;
+        self
+    }
+
+    pub fn with_prefiltered(mut self, size: u32, mips: u32) -> Self {
+        self.prefiltered_size = size;
+        self.prefiltered_mips = mips.clamp(1, size.max(1).ilog2() + 1);
+        self
+    }
+
+    pub fn with_brdf_lut_size(mut self, size: u32) -> Self {
+        self.brdf_lut_size = size;
+        self
+    }
+
+    /// check that the maps can be created with the limits of the device
+    fn validate(&self, limits: &wgpu::Limits) -> Result<(), EnvironmentError> {
+        let max = limits.max_texture_dimension_2d;
+        let sizes = [
+            ("size", self.size),
+            ("irradiance size", self.irradiance_size),
+            ("prefiltered size", self.prefiltered_size),
+            ("brdf lut size", self.brdf_lut_size),
+        ];
+        for (name, size) in sizes {
+            if size == 0 || size > max {
+                return Err(EnvironmentError::Config(format!(
+                    "{name} {size} is not in 1..={max}"
+                )));
+            }
+        }
+        let max_mips = self.prefiltered_size.ilog2() + 1;
+        if !(1..=max_mips).contains(&self.prefiltered_mips) {
+            return Err(EnvironmentError::Config(format!(
+                "{} prefiltered mips are not in 1..={max_mips}",
+                self.prefiltered_mips
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Hdr(HdrError),
+    /// a size of the config is 0, too large for the device or has invalid mips
+    Config(String),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvironmentError::Hdr(e) => e.fmt(f),
+            EnvironmentError::Config(e) => write!(f, "invalid environment config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for EnvironmentError {}
+
+impl From<HdrError> for EnvironmentError {
+    fn from(e: HdrError) -> Self {
+        EnvironmentError::Hdr(e)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct EnvironmentUniform {
+    intensity: f32,
+    max_lod: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct PrefilterUniform {
+    roughness: f32,
+    source_size: f32,
+}
+
+/// environment cubemap and the image based lighting maps computed from it
+#[derive(Debug)]
+pub struct Environment {
+    /// Rgba16Float cubemap with a full mip chain
+    pub cubemap: Texture,
+    /// cosine convolution of the cubemap, for the diffuse light
+    pub irradiance: Texture,
+    /// GGX convolution of the cubemap, one roughness per mip
+    pub prefiltered: Texture,
+    /// split sum scale and bias of f0, in red and green
+    pub brdf_lut: Texture,
+    buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    max_lod: f32,
+}
+
+const ENVIRONMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+impl Environment {
+    pub fn load<P: AsRef<Path>>(
+        gpu: &Gpu,
+        path: P,
+        config: EnvironmentConfig,
+    ) -> Result<Self, EnvironmentError> {
+        Self::from_hdr(gpu, &HdrImage::load(path)?, config)
+    }
+
+    /// convert an equirectangular image to a cubemap and precompute the lighting maps
+    ///
+    /// images larger than the texture limit of the device are downsampled first
+    pub fn from_hdr(
+        gpu: &Gpu,
+        image: &HdrImage,
+        config: EnvironmentConfig,
+    ) -> Result<Self, EnvironmentError> {
+        let device = &gpu.device;
+        let queue = &gpu.queue;
+        let limits = gpu.limits();
+        config.validate(&limits)?;
+
+        let max = limits.max_texture_dimension_2d;
+        let mut downsampled: Option<HdrImage> = None;
+        loop {
+            let current = downsampled.as_ref().unwrap_or(image);
+            if current.width <= max && current.height <= max {
+                break;
+            }
+            downsampled = Some(current.downsample());
+        }
+        let image = downsampled.as_ref().unwrap_or(image);
+
+        let equirect = TextureBuilder::new()
+            .with_format(wgpu::TextureFormat::Rgba32Float)
+            .with_data(bytemuck::cast_slice(&image.data))
+            .build((image.width, image.height), device, queue);
+
+        let cube = |size: u32, mips: u32| {
+            TextureBuilder::new()
+                .with_format(ENVIRONMENT_FORMAT)
+                .with_usages(wgpu::TextureUsages::STORAGE_BINDING)
+                .with_min_filter(wgpu::FilterMode::Linear)
+                .with_cube()
+                .with_mip_levels(mips)
+                .build((size, size), device, queue)
+        };
+        let cubemap = cube(config.size, config.size.ilog2() + 1);
+        let irradiance = cube(config.irradiance_size, 1);
+        let prefiltered = cube(config.prefiltered_size, config.prefiltered_mips);
+        let brdf_lut = TextureBuilder::new()
+            .with_format(ENVIRONMENT_FORMAT)
+            .with_usages(wgpu::TextureUsages::STORAGE_BINDING)
+            .with_min_filter(wgpu::FilterMode::Linear)
+            .build((config.brdf_lut_size, config.brdf_lut_size), device, queue);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("environment_encoder"),
+        });
+        {
+            let mut kernels = Kernels::new(device);
+
+            // equirectangular to cube, then the mips of the cube
+            let equirect_view = equirect.texture.create_view(&Default::default());
+            kernels.dispatch(
+                &mut encoder,
+                EQUIRECT_WGSL,
+                &[
+                    (
+                        texture_binding(false, wgpu::TextureViewDimension::D2),
+                        wgpu::BindingResource::TextureView(&equirect_view),
+                    ),
+                    (
+                        storage_binding(wgpu::TextureViewDimension::D2Array),
+                        wgpu::BindingResource::TextureView(&mip_view(&cubemap, 0)),
+                    ),
+                ],
+                (config.size, config.size, 6),
+            );
+            for mip in 1..cubemap.texture.mip_level_count() {
+                let size = (config.size >> mip).max(1);
+                kernels.dispatch(
+                    &mut encoder,
+                    DOWNSAMPLE_WGSL,
+                    &[
+                        (
+                            texture_binding(true, wgpu::TextureViewDimension::D2Array),
+                            wgpu::BindingResource::TextureView(&mip_view(&cubemap, mip - 1)),
+                        ),
+                        (
+                            storage_binding(wgpu::TextureViewDimension::D2Array),
+                            wgpu::BindingResource::TextureView(&mip_view(&cubemap, mip)),
+                        ),
+                    ],
+                    (size, size, 6),
+                );
+            }
+
+            kernels.dispatch(
+                &mut encoder,
+                IRRADIANCE_WGSL,
+                &[
+                    (
+                        texture_binding(true, wgpu::TextureViewDimension::Cube),
+                        wgpu::BindingResource::TextureView(&cubemap.view),
+                    ),
+                    (
+                        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        wgpu::BindingResource::Sampler(&sampler),
+                    ),
+                    (
+                        storage_binding(wgpu::TextureViewDimension::D2Array),
+                        wgpu::BindingResource::TextureView(&mip_view(&irradiance, 0)),
+                    ),
+                ],
+                (config.irradiance_size, config.irradiance_size, 6),
+            );
+
+            let max_mip = (config.prefiltered_mips - 1).max(1) as f32;
+            for mip in 0..config.prefiltered_mips {
+                let size = (config.prefiltered_size >> mip).max(1);
+                let uniform = PrefilterUniform {
+                    roughness: mip as f32 / max_mip,
+                    source_size: config.size as f32,
+                };
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("prefilter_buffer"),
+                    contents: bytemuck::bytes_of(&uniform),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                kernels.dispatch(
+                    &mut encoder,
+                    PREFILTER_WGSL,
+                    &[
+                        (
+                            texture_binding(true, wgpu::TextureViewDimension::Cube),
+                            wgpu::BindingResource::TextureView(&cubemap.view),
+                        ),
+                        (
+                            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            wgpu::BindingResource::Sampler(&sampler),
+                        ),
+                        (
+                            storage_binding(wgpu::TextureViewDimension::D2Array),
+                            wgpu::BindingResource::TextureView(&mip_view(&prefiltered, mip)),
+                        ),
+                        (
+                            wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            buffer.as_entire_binding(),
+                        ),
+                    ],
+                    (size, size, 6),
+                );
+            }
+
+            kernels.dispatch(
+                &mut encoder,
+                BRDF_WGSL,
+                &[(
+                    storage_binding(wgpu::TextureViewDimension::D2),
+                    wgpu::BindingResource::TextureView(&brdf_lut.view),
+                )],
+                (config.brdf_lut_size, config.brdf_lut_size, 1),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let max_lod = (config.prefiltered_mips - 1) as f32;
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("environment_buffer"),
+            contents: bytemuck::bytes_of(&EnvironmentUniform {
+                intensity: 1.0,
+                max_lod,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Ok(Self {
+            cubemap,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            buffer,
+            sampler,
+            max_lod,
+        })
+    }
+
+    /// scale of the light coming from the environment
+    pub fn set_intensity(&self, queue: &wgpu::Queue, intensity: f32) {
+        let uniform = EnvironmentUniform {
+            intensity,
+            max_lod: self.max_lod,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// entries of the lighting maps in a bind group layout, starting at `first_binding`
+    pub fn layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 5] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        [
+            texture(first_binding, wgpu::TextureViewDimension::Cube),
+            texture(first_binding + 1, wgpu::TextureViewDimension::Cube),
+            texture(first_binding + 2, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    /// resources matching layout_entries
+    pub fn bind_group_entries(&self, first_binding: u32) -> [wgpu::BindGroupEntry<'_>; 5] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.prefiltered.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::TextureView(&self.brdf_lut.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 4,
+                resource: self.buffer.as_entire_binding(),
+            },
+        ]
+    }
+}
+
+/// view of one mip of a cubemap as a 2d array, to write it from a compute shader
+fn mip_view(texture: &Texture, mip: u32) -> wgpu::TextureView {
+    texture.texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("environment_mip_view"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+/// sampled float texture, the float32 ones are not filterable
+fn texture_binding(
+    filterable: bool,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindingType {
+    wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable },
+        view_dimension,
+        multisampled: false,
+    }
+}
+
+/// texture of ENVIRONMENT_FORMAT written by a kernel
+fn storage_binding(view_dimension: wgpu::TextureViewDimension) -> wgpu::BindingType {
+    wgpu::BindingType::StorageTexture {
+        access: wgpu::StorageTextureAccess::WriteOnly,
+        format: ENVIRONMENT_FORMAT,
+        view_dimension,
+    }
+}
+
+/// compute pipelines of the precomputations, created on first use
+struct Kernels<'a> {
+    device: &'a wgpu::Device,
+    pipelines: Vec<(&'static str, wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+}
+
+impl<'a> Kernels<'a> {
+    fn new(device: &'a wgpu::Device) -> Self {
+        Self {
+            device,
+            pipelines: Vec::new(),
+        }
+    }
+
+    /// run `source` once per texel of a size.0 x size.1 x size.2 grid, the resources are
+    /// bound in order with their binding types
+    ///
+    /// the layout is explicit, a derived one would expect filterable float32 textures
+    fn dispatch(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &'static str,
+        bindings: &[(wgpu::BindingType, wgpu::BindingResource)],
+        size: (u32, u32, u32),
+    ) {
+        let index = match self.pipelines.iter().position(|(s, ..)| *s == source) {
+            Some(index) => index,
+            None => {
+                let layout_entries: Vec<_> = bindings
+                    .iter()
+                    .enumerate()
+                    .map(|(binding, (ty, _))| wgpu::BindGroupLayoutEntry {
+                        binding: binding as u32,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: *ty,
+                        count: None,
+                    })
+                    .collect();
+                let bind_group_layout =
+                    self.device
+                        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                            label: Some("environment_bind_group_layout"),
+                            entries: &layout_entries,
+                        });
+                let pipeline_layout =
+                    self.device
+                        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some("environment_pipeline_layout"),
+                            bind_group_layouts: &[&bind_group_layout],
+                            push_constant_ranges: &[],
+                        });
+                let shader = self
+                    .device
+                    .create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some("environment_shader"),
+                        source: wgpu::ShaderSource::Wgsl(format!("{CUBE_WGSL}{source}").into()),
+                    });
+                let pipeline =
+                    self.device
+                        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                            label: Some("environment_pipeline"),
+                            layout: Some(&pipeline_layout),
+                            module: &shader,
+                            entry_point: "main",
+                        });
+                self.pipelines.push((source, bind_group_layout, pipeline));
+                self.pipelines.len() - 1
+            }
+        };
+        let (_, bind_group_layout, pipeline) = &self.pipelines[index];
+        let entries: Vec<_> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, (_, resource))| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.clone(),
+            })
+            .collect();
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment_bind_group"),
+            layout: bind_group_layout,
+            entries: &entries,
+        });
+
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("environment_pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(size.0.div_ceil(8), size.1.div_ceil(8), size.2);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct SkyboxUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    lod: f32,
+    intensity: f32,
+    _pad: [f32; 2],
+}
+
+/// environment cubemap drawn on the far plane, behind everything in the depth buffer
+#[derive(Debug)]
+pub struct SkyboxRenderer {
+    /// mip of the cubemap, to blur the background
+    pub lod: f32,
+    pub intensity: f32,
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SkyboxRenderer {
+    /// skybox drawn to the scene target
+    pub fn new(
+        gpu: &Gpu,
+        environment: &Environment,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        Self::with_formats(gpu, environment, gpu.get_scene_format(), depth_format)
+    }
+
+    pub fn with_formats(
+        gpu: &Gpu,
+        environment: &Environment,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let device = &gpu.device;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("skybox_buffer"),
+            size: std::mem::size_of::<SkyboxUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.cubemap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("skybox_shader"),
+            source: wgpu::ShaderSource::Wgsl(SKYBOX_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // only where nothing was drawn, the depth buffer being cleared to 1
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            lod: 0.0,
+            intensity: 1.0,
+            pipeline,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// upload the camera rotation and the settings
+    pub fn prepare(&self, gpu: &Gpu, camera: &CameraUniform) {
+        // the translation of the view is dropped, the sky is infinitely far
+        let mut view = Mat4::from_cols_array_2d(&camera.view);
+        view.w_axis = glam::Vec4::W;
+        let view_proj = Mat4::from_cols_array_2d(&camera.proj) * view;
+        let uniform = SkyboxUniform {
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            lod: self.lod,
+            intensity: self.intensity,
+            _pad: [0.0; 2],
+        };
+        gpu.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(resolution: &str) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{resolution}\n").into_bytes()
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        let mut bytes = header("-Y 1 +X 2");
+        bytes.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = HdrImage::from_bytes(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.data[4..], [0.0, 0.0, 0.0, 1.0]);
+        assert!((image.data[0] - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn rejects_sizes_larger_than_the_data() {
+        for resolution in [
+            "-Y 65536 +X 65536",
+            "-Y 4294967295 +X 4294967295",
+            "-Y 0 +X 8",
+        ] {
+            let mut bytes = header(resolution);
+            bytes.extend([0; 64]);
+            assert!(matches!(
+                HdrImage::from_bytes(&bytes),
+                Err(HdrError::Format(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn downsample_averages_blocks() {
+        let image = HdrImage {
+            width: 3,
+            height: 2,
+            data: (0..6).flat_map(|i| [i as f32; 4]).collect(),
+        };
+        let half = image.downsample();
+        assert_eq!((half.width, half.height), (1, 1));
+        assert_eq!(half.data, [2.0; 4]);
+    }
+}
@@ -0,0 +1,679 @@
+use std::path::Path;
+
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4Swizzles};
+use gltf::json::validation::Error as ValidationError;
+use log::warn;
+
+use crate::animation::{AnimationClip, Channel, Interpolation, Property, Skin};
+use crate::camera::{OrthographicCamera, PerspectiveCamera};
+use crate::graphics::{skinned_vertex, vertex, SkinnedVertex, Texture, TextureBuilder};
+use crate::mesh::{Indices, Mesh, MeshData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// alpha tested against the cutoff
+    Mask,
+    Blend,
+}
+
+/// metallic-roughness material, texture fields are indices in GltfScene::textures
+#[derive(Debug, Clone)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// roughness in G, metallic in B
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+/// displacements of a morph target, one per vertex
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+#[derive(Debug)]
+pub struct GltfPrimitive {
+    pub data: MeshData,
+    /// made of SkinnedVertex when the primitive is skinned, of Vertex otherwise
+    pub mesh: Mesh,
+    pub skinned: bool,
+    pub morph_targets: Vec<MorphTarget>,
+    /// index in GltfScene::materials
+    pub material: Option<usize>,
+}
+
+impl GltfPrimitive {
+    /// geometry deformed by the morph targets on the cpu
+    pub fn morphed(&self, weights: &[f32]) -> MeshData {
+        let mut data = self.data.clone();
+        for (target, &w) in self.morph_targets.iter().zip(weights) {
+            if w == 0.0 {
+                continue;
+            }
+            for (i, v) in data.vertices.iter_mut().enumerate() {
+                if let Some(d) = target.positions.get(i) {
+                    let p = Vec3::from_slice(&v.pos[..3]) + *d * w;
+                    v.pos = p.extend(1.0).to_array();
+                }
+                if let Some(d) = target.normals.get(i) {
+                    v.normal = (Vec3::from(v.normal) + *d * w).to_array();
+                }
+            }
+        }
+        for v in data.vertices.iter_mut() {
+            v.normal = Vec3::from(v.normal).normalize_or_zero().to_array();
+        }
+        data
+    }
+}
+
+#[derive(Debug)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// transform relative to the parent
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    /// transform relative to the scene root
+    pub world_transform: Mat4,
+    /// index in GltfScene::meshes
+    pub mesh: Option<usize>,
+    /// index in GltfScene::cameras
+    pub camera: Option<usize>,
+    /// index in GltfScene::skins
+    pub skin: Option<usize>,
+    /// morph target weights of the mesh
+    pub weights: Vec<f32>,
+}
+
+impl GltfNode {
+    /// transform relative to the parent
+    pub fn local_transform(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// camera placed by a node of the scene
+#[derive(Debug, Clone, Copy)]
+pub enum SceneCamera {
+    Perspective(PerspectiveCamera),
+    Orthographic(OrthographicCamera),
+}
+
+/// content of a glTF file uploaded on the gpu
+#[derive(Debug)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    /// None when the image could not be decoded
+    pub textures: Vec<Option<Texture>>,
+    pub nodes: Vec<GltfNode>,
+    /// root nodes of the default scene
+    pub roots: Vec<usize>,
+    pub cameras: Vec<SceneCamera>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl GltfScene {
+    /// load a .gltf or .glb file, external buffers and images are read next to it
+    pub fn load(
+        path: impl AsRef<Path>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, gltf::Error> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(gltf::Error::Io)?;
+        Self::from_slice(&bytes, path.parent(), device, queue)
+    }
+
+    /// load a glTF from memory, external references are resolved from base_dir
+    pub fn from_slice(
+        bytes: &[u8],
+        base_dir: Option<&Path>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, gltf::Error> {
+        let gltf::Gltf { document, blob } = open(bytes)?;
+        // no extension is supported yet
+        for ext in document.extensions_used() {
+            warn!(target: "gltf", "unsupported extension {ext} is ignored");
+        }
+
+        let buffers = gltf::import_buffers(&document, base_dir, blob)?;
+
+        let materials: Vec<PbrMaterial> = document.materials().map(read_material).collect();
+        let textures = read_textures(&document, &materials, &buffers, base_dir, device, queue);
+
+        let meshes = document
+            .meshes()
+            .map(|mesh| GltfMesh {
+                name: mesh.name().map(String::from),
+                primitives: mesh
+                    .primitives()
+                    .filter_map(|p| read_primitive(&p, &buffers, device))
+                    .collect(),
+            })
+            .collect();
+
+        let mut nodes: Vec<GltfNode> = document
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                let weights = node
+                    .weights()
+                    .or(node.mesh().and_then(|m| m.weights()))
+                    .map(|w| w.to_vec())
+                    .unwrap_or_default();
+                GltfNode {
+                    name: node.name().map(String::from),
+                    parent: None,
+                    children: node.children().map(|c| c.index()).collect(),
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale),
+                    world_transform: Mat4::IDENTITY,
+                    mesh: node.mesh().map(|m| m.index()),
+                    camera: None,
+                    skin: node.skin().map(|s| s.index()),
+                    weights,
+                }
+            })
+            .collect();
+        for i in 0..nodes.len() {
+            for c in nodes[i].children.clone() {
+                nodes[c].parent = Some(i);
+            }
+        }
+
+        let roots: Vec<usize> = match document.default_scene().or(document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+            None => (0..nodes.len())
+                .filter(|&i| nodes[i].parent.is_none())
+                .collect(),
+        };
+
+        let skins = document
+            .skins()
+            .map(|skin| read_skin(&skin, &buffers))
+            .collect();
+        let animations = document
+            .animations()
+            .map(|animation| read_animation(&animation, &buffers))
+            .collect();
+
+        let mut scene = Self {
+            meshes,
+            materials,
+            textures,
+            nodes,
+            roots,
+            cameras: vec![],
+            skins,
+            animations,
+        };
+        scene.update_transforms();
+
+        for node in document.nodes() {
+            if let Some(camera) = node.camera() {
+                let transform = scene.nodes[node.index()].world_transform;
+                scene.nodes[node.index()].camera = Some(scene.cameras.len());
+                scene.cameras.push(read_camera(&camera, transform));
+            }
+        }
+
+        Ok(scene)
+    }
+
+    /// recompute the world transforms from the local ones
+    pub fn update_transforms(&mut self) {
+        let mut stack: Vec<(usize, Mat4)> =
+            self.roots.iter().map(|&r| (r, Mat4::IDENTITY)).collect();
+        while let Some((i, parent)) = stack.pop() {
+            let world = parent * self.nodes[i].local_transform();
+            self.nodes[i].world_transform = world;
+            stack.extend(self.nodes[i].children.iter().map(|&c| (c, world)));
+        }
+    }
+
+    /// world transform of each joint multiplied by its inverse bind matrix
+    /// (the transform of the skinned mesh node is ignored, as required by glTF)
+    pub fn joint_matrices(&self, skin: usize) -> Vec<Mat4> {
+        let skin = &self.skins[skin];
+        skin.joints
+            .iter()
+            .zip(&skin.inverse_bind_matrices)
+            .map(|(&joint, ibm)| self.nodes[joint].world_transform * *ibm)
+            .collect()
+    }
+
+    /// nodes with a mesh, with their world transform
+    pub fn mesh_instances(&self) -> impl Iterator<Item = (&GltfMesh, Mat4)> {
+        self.nodes
+            .iter()
+            .filter_map(|n| n.mesh.map(|m| (&self.meshes[m], n.world_transform)))
+    }
+}
+
+/// parse the document, unsupported required extensions are reported as warnings
+fn open(bytes: &[u8]) -> Result<gltf::Gltf, gltf::Error> {
+    match gltf::Gltf::from_slice(bytes) {
+        Err(gltf::Error::Validation(errors))
+            if errors
+                .iter()
+                .all(|(_, e)| matches!(e, ValidationError::Unsupported)) =>
+        {
+            for (path, _) in &errors {
+                warn!(target: "gltf", "unsupported required extension {path}");
+            }
+            gltf::Gltf::from_slice_without_validation(bytes)
+        }
+        result => result,
+    }
+}
+
+fn read_material(material: gltf::Material) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    PbrMaterial {
+        name: material.name().map(String::from),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|t| t.texture().index()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|t| t.texture().index()),
+        normal_texture: material.normal_texture().map(|t| t.texture().index()),
+        normal_scale: material.normal_texture().map_or(1.0, |t| t.scale()),
+        occlusion_texture: material.occlusion_texture().map(|t| t.texture().index()),
+        occlusion_strength: material.occlusion_texture().map_or(1.0, |t| t.strength()),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material.emissive_texture().map(|t| t.texture().index()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+    }
+}
+
+/// build the textures with TextureBuilder, color textures are sRGB
+fn read_textures(
+    document: &gltf::Document,
+    materials: &[PbrMaterial],
+    buffers: &[gltf::buffer::Data],
+    base_dir: Option<&Path>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Vec<Option<Texture>> {
+    let images: Vec<Option<gltf::image::Data>> = document
+        .images()
+        .map(|image| {
+            gltf::image::Data::from_source(image.source(), base_dir, buffers)
+                .map_err(|e| warn!(target: "gltf", "failed to load image {}: {e}", image.index()))
+                .ok()
+        })
+        .collect();
+
+    document
+        .textures()
+        .map(|texture| {
+            let image = images[texture.source().index()].as_ref()?;
+            let pixels = match to_rgba8(image) {
+                Some(p) => p,
+                None => {
+                    warn!(target: "gltf", "unsupported image format {:?}", image.format);
+                    return None;
+                }
+            };
+
+            let index = Some(texture.index());
+            let srgb = materials
+                .iter()
+                .any(|m| m.base_color_texture == index || m.emissive_texture == index);
+            let format = if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            };
+
+            let sampler = texture.sampler();
+            let filter = |linear: bool| {
+                if linear {
+                    wgpu::FilterMode::Linear
+                } else {
+                    wgpu::FilterMode::Nearest
+                }
+            };
+            let mag_linear = !matches!(
+                sampler.mag_filter(),
+                Some(gltf::texture::MagFilter::Nearest)
+            );
+            let min_linear = !matches!(
+                sampler.min_filter(),
+                Some(
+                    gltf::texture::MinFilter::Nearest
+                        | gltf::texture::MinFilter::NearestMipmapNearest
+                        | gltf::texture::MinFilter::NearestMipmapLinear
+                )
+            );
+            let address_mode = match sampler.wrap_s() {
+                gltf::texture::WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+                gltf::texture::WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+                gltf::texture::WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+            };
+
+            Some(
+                TextureBuilder::new()
+                    .with_format(format)
+                    .with_address_mode(address_mode)
+                    .with_mag_filter(filter(mag_linear))
+                    .with_min_filter(filter(min_linear))
+                    .with_data(&pixels)
+                    .build((image.width, image.height), device, queue),
+            )
+        })
+        .collect()
+}
+
+/// convert the decoded image to 8 bits RGBA, None for the float formats
+fn to_rgba8(image: &gltf::image::Data) -> Option<Vec<u8>> {
+    use gltf::image::Format;
+
+    // keep the most significant byte of the 16 bits channels (little endian)
+    let high_bytes = |channels: usize| -> Vec<u8> {
+        image
+            .pixels
+            .chunks_exact(2 * channels)
+            .flat_map(|px| {
+                let mut rgba = [0, 0, 0, 255];
+                for c in 0..channels {
+                    rgba[c] = px[2 * c + 1];
+                }
+                if channels == 1 {
+                    rgba = [rgba[0], rgba[0], rgba[0], 255];
+                }
+                rgba
+            })
+            .collect()
+    };
+
+    Some(match image.format {
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R8G8B8 => image
+            .pixels
+            .chunks_exact(3)
+            .flat_map(|px| [px[0], px[1], px[2], 255])
+            .collect(),
+        Format::R8G8 => image
+            .pixels
+            .chunks_exact(2)
+            .flat_map(|px| [px[0], px[1], 0, 255])
+            .collect(),
+        Format::R8 => image.pixels.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        Format::R16 => high_bytes(1),
+        Format::R16G16 => high_bytes(2),
+        Format::R16G16B16 => high_bytes(3),
+        Format::R16G16B16A16 => high_bytes(4),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => return None,
+    })
+}
+
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    device: &wgpu::Device,
+) -> Option<GltfPrimitive> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        warn!(target: "gltf", "primitive mode {:?} is not supported", primitive.mode());
+        return None;
+    }
+
+    let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| d.0.as_slice()));
+    let Some(positions) = reader.read_positions() else {
+        warn!(target: "gltf", "primitive without POSITION attribute skipped");
+        return None;
+    };
+    let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+    let normals: Option<Vec<Vec3>> = reader.read_normals().map(|n| n.map(Vec3::from).collect());
+    let uvs: Vec<Vec2> = match reader.read_tex_coords(0) {
+        Some(t) => t.into_f32().map(Vec2::from).collect(),
+        None => vec![Vec2::ZERO; positions.len()],
+    };
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(i) => i.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let joints: Option<Vec<[u16; 4]>> = reader.read_joints(0).map(|j| j.into_u16().collect());
+    let weights: Option<Vec<[f32; 4]>> = reader.read_weights(0).map(|w| w.into_f32().collect());
+    let counts = [
+        ("NORMAL", normals.as_ref().map(Vec::len)),
+        ("TEXCOORD_0", Some(uvs.len())),
+        ("JOINTS_0", joints.as_ref().map(Vec::len)),
+        ("WEIGHTS_0", weights.as_ref().map(Vec::len)),
+    ];
+    let mismatch = counts
+        .into_iter()
+        .find_map(|(name, n)| n.filter(|&n| n != positions.len()).map(|n| (name, n)));
+    if let Some((name, n)) = mismatch {
+        warn!(target: "gltf", "primitive skipped, {name} has {n} entries for {} positions", positions.len());
+        return None;
+    }
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        warn!(target: "gltf", "primitive skipped, index {i} out of {} positions", positions.len());
+        return None;
+    }
+
+    let normals = normals.unwrap_or_else(|| {
+        let mut generated = vec![Vec3::ZERO; positions.len()];
+        for tri in indices.chunks_exact(3) {
+            let p: Vec<Vec3> = tri.iter().map(|&i| positions[i as usize]).collect();
+            let n = (p[1] - p[0]).cross(p[2] - p[0]);
+            for &i in tri {
+                generated[i as usize] += n;
+            }
+        }
+        generated.iter().map(|n| n.normalize_or_zero()).collect()
+    });
+
+    let vertices: Vec<_> = positions
+        .iter()
+        .zip(&normals)
+        .zip(&uvs)
+        .map(|((p, n), uv)| vertex(p.extend(1.0), *n, *uv))
+        .collect();
+
+    let (data, mesh, skinned) = match joints.zip(weights) {
+        Some((joints, weights)) => {
+            let skinned: Vec<SkinnedVertex> = vertices
+                .iter()
+                .zip(joints.iter().zip(&weights))
+                .map(|(v, (j, w))| skinned_vertex(*v, j.map(u32::from), *w))
+                .collect();
+            let data = MeshData::new(vertices, indices);
+            let mut mesh = Mesh::new(device, &skinned, &Indices::compact(data.indices.clone()));
+            mesh.aabb = Some(data.aabb);
+            (data, mesh, true)
+        }
+        None => {
+            let data = MeshData::new(vertices, indices);
+            let mesh = Mesh::from_data(device, &data);
+            (data, mesh, false)
+        }
+    };
+
+    let morph_targets = reader
+        .read_morph_targets()
+        .map(|(positions, normals, _)| MorphTarget {
+            positions: positions
+                .map(|p| p.map(Vec3::from).collect())
+                .unwrap_or_default(),
+            normals: normals
+                .map(|n| n.map(Vec3::from).collect())
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    Some(GltfPrimitive {
+        data,
+        mesh,
+        skinned,
+        morph_targets,
+        material: primitive.material().index(),
+    })
+}
+
+/// place the camera with the node transform, glTF cameras look down -Z
+fn read_camera(camera: &gltf::Camera, transform: Mat4) -> SceneCamera {
+    let eye = transform.w_axis.xyz();
+    let target = eye - transform.z_axis.xyz().normalize_or_zero();
+    let up = transform.y_axis.xyz().normalize_or_zero();
+
+    match camera.projection() {
+        gltf::camera::Projection::Perspective(p) => {
+            let default = PerspectiveCamera::default();
+            SceneCamera::Perspective(PerspectiveCamera {
+                eye,
+                target,
+                up,
+                fovy: p.yfov(),
+                aspect: p.aspect_ratio().unwrap_or(default.aspect),
+                znear: p.znear(),
+                zfar: p.zfar().unwrap_or(default.zfar),
+            })
+        }
+        gltf::camera::Projection::Orthographic(o) => {
+            SceneCamera::Orthographic(OrthographicCamera {
+                eye,
+                target,
+                up,
+                height: o.ymag() * 2.0,
+                aspect: o.xmag() / o.ymag(),
+                znear: o.znear(),
+                zfar: o.zfar(),
+            })
+        }
+    }
+}
+
+fn read_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Skin {
+    let joints: Vec<usize> = skin.joints().map(|j| j.index()).collect();
+    let reader = skin.reader(|b| buffers.get(b.index()).map(|d| d.0.as_slice()));
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+        Some(m) => m.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+        None => vec![Mat4::IDENTITY; joints.len()],
+    };
+    Skin {
+        name: skin.name().map(String::from),
+        joints,
+        inverse_bind_matrices,
+        skeleton: skin.skeleton().map(|n| n.index()),
+    }
+}
+
+fn read_animation(animation: &gltf::Animation, buffers: &[gltf::buffer::Data]) -> AnimationClip {
+    use gltf::animation::util::ReadOutputs;
+
+    let channels: Vec<Channel> = animation
+        .channels()
+        .filter_map(|channel| {
+            let reader = channel.reader(|b| buffers.get(b.index()).map(|d| d.0.as_slice()));
+            let times: Vec<f32> = reader.read_inputs()?.collect();
+            let (property, values, width): (Property, Vec<f32>, usize) = match reader
+                .read_outputs()?
+            {
+                ReadOutputs::Translations(t) => (Property::Translation, t.flatten().collect(), 3),
+                ReadOutputs::Rotations(r) => {
+                    (Property::Rotation, r.into_f32().flatten().collect(), 4)
+                }
+                ReadOutputs::Scales(s) => (Property::Scale, s.flatten().collect(), 3),
+                ReadOutputs::MorphTargetWeights(w) => {
+                    let values: Vec<f32> = w.into_f32().collect();
+                    let width = values.len() / times.len().max(1);
+                    (Property::MorphWeights, values, width)
+                }
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            // cubic spline keyframes hold 3 values
+            let width = if interpolation == Interpolation::CubicSpline
+                && property == Property::MorphWeights
+            {
+                width / 3
+            } else {
+                width
+            };
+            if times.is_empty() || width == 0 {
+                return None;
+            }
+            Some(Channel {
+                node: channel.target().node().index(),
+                property,
+                interpolation,
+                times,
+                values,
+                width,
+            })
+        })
+        .collect();
+
+    let duration = channels
+        .iter()
+        .filter_map(|c| c.times.last().copied())
+        .fold(0.0, f32::max);
+
+    AnimationClip {
+        name: animation.name().map(String::from),
+        channels,
+        duration,
+    }
+}
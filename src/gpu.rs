@@ -1,12 +1,167 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use futures_lite::future::block_on;
 use log::{error, info};
 
+use crate::blit::{BlitMode, Blitter};
+use crate::graphics::Texture;
+use crate::render_target::RenderTargetSize;
+
+/// errors that can happen while building the gpu abstraction
+#[derive(Debug)]
+pub enum GpuError {
+    /// the surface could not be created for the window
+    CreateSurface(wgpu::CreateSurfaceError),
+    /// no adapter matches the requested backends and device type
+    NoAdapter {
+        backends: wgpu::Backends,
+        device_type: wgpu::DeviceType,
+    },
+    /// the adapter lacks some of the required features
+    MissingFeatures(wgpu::Features),
+    /// some of the requested limits exceed the adapter ones (name, requested, allowed)
+    UnsupportedLimits(Vec<(&'static str, u64, u64)>),
+    /// the api trace directory could not be created
+    TraceDir(std::io::Error),
+    /// the device request has been refused by the adapter
+    RequestDevice(wgpu::RequestDeviceError),
+    /// the surface has no supported format with the adapter
+    NoSurfaceFormat,
+    /// none of the preferred surface formats is supported
+    UnsupportedFormat {
+        preferred: Vec<wgpu::TextureFormat>,
+        supported: Vec<wgpu::TextureFormat>,
+    },
+    /// a view format is not compatible with the surface format
+    UnsupportedViewFormat {
+        format: wgpu::TextureFormat,
+        view_format: wgpu::TextureFormat,
+    },
+    /// the alpha mode is not supported by the surface
+    UnsupportedAlphaMode {
+        requested: wgpu::CompositeAlphaMode,
+        supported: Vec<wgpu::CompositeAlphaMode>,
+    },
+    /// some of the requested surface usages are not supported
+    UnsupportedSurfaceUsages {
+        requested: wgpu::TextureUsages,
+        supported: wgpu::TextureUsages,
+    },
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateSurface(e) => write!(f, "failed to create the surface: {e}"),
+            Self::NoAdapter {
+                backends,
+                device_type,
+            } => write!(
+                f,
+                "no adapter found with backends {backends:?} and device type {device_type:?}"
+            ),
+            Self::MissingFeatures(missing) => {
+                write!(f, "the adapter lacks the required features {missing:?}")
+            }
+            Self::UnsupportedLimits(failed) => {
+                write!(f, "the adapter does not support the requested limits:")?;
+                for (name, requested, allowed) in failed {
+                    write!(f, "\n\t{name}: requested {requested}, allowed {allowed}")?;
+                }
+                Ok(())
+            }
+            Self::TraceDir(e) => write!(f, "failed to create the trace directory: {e}"),
+            Self::RequestDevice(e) => write!(f, "failed to request the device: {e}"),
+            Self::NoSurfaceFormat => {
+                write!(f, "the surface supports no format with the selected adapter")
+            }
+            Self::UnsupportedFormat {
+                preferred,
+                supported,
+            } => write!(
+                f,
+                "none of the preferred surface formats {preferred:?} is supported (supported: {supported:?})"
+            ),
+            Self::UnsupportedViewFormat {
+                format,
+                view_format,
+            } => write!(
+                f,
+                "view format {view_format:?} is not supported for surface format {format:?}"
+            ),
+            Self::UnsupportedAlphaMode {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "surface alpha mode {requested:?} is not supported (supported: {supported:?})"
+            ),
+            Self::UnsupportedSurfaceUsages {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "surface usages {requested:?} are not supported (supported: {supported:?})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreateSurface(e) => Some(e),
+            Self::TraceDir(e) => Some(e),
+            Self::RequestDevice(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// what to do with the wgpu errors that are not captured by an error scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// panic like wgpu does by default
+    Panic,
+    /// log the error and keep running
+    Log,
+    /// log the error and store it, use Gpu::take_errors to get them back
+    /// (shown as toasts when the egui feature is enabled)
+    /// only the last MAX_COLLECTED_ERRORS are kept
+    Collect,
+}
+
+/// number of errors kept by ErrorPolicy::Collect, the oldest are dropped
+pub const MAX_COLLECTED_ERRORS: usize = 256;
+
+/// limits requested for the device
+#[derive(Debug, Clone)]
+pub enum GpuLimits {
+    /// use these exact limits
+    Custom(wgpu::Limits),
+    /// use the best limits supported by the adapter
+    Adapter,
+}
+
 #[derive(Debug, Clone)]
 pub struct GpuBuilder {
     pub(crate) backends: wgpu::Backends,
     pub(crate) device_type: wgpu::DeviceType,
     pub(crate) present_mode: wgpu::PresentMode,
     pub(crate) features: wgpu::Features,
-    pub(crate) limits: wgpu::Limits,
+    pub(crate) optional_features: wgpu::Features,
+    pub(crate) limits: GpuLimits,
+    pub(crate) surface_formats: Vec<wgpu::TextureFormat>,
+    pub(crate) view_formats: Vec<wgpu::TextureFormat>,
+    pub(crate) alpha_mode: Option<wgpu::CompositeAlphaMode>,
+    pub(crate) surface_usages: wgpu::TextureUsages,
+    pub(crate) error_policy: ErrorPolicy,
+    pub(crate) trace_dir: Option<PathBuf>,
 }
 
 impl GpuBuilder {
@@ -24,13 +179,44 @@ impl GpuBuilder {
         self
     }
 
-    pub fn with_feature(mut self, f: wgpu::Features) -> Self {
+    /// same as require_features
+    pub fn with_feature(self, f: wgpu::Features) -> Self {
+        self.require_features(f)
+    }
+
+    /// features the device must have, the build fails if the adapter lacks them
+    pub fn require_features(mut self, f: wgpu::Features) -> Self {
         self.features |= f;
         self
     }
 
+    /// features enabled only if the adapter supports them
+    /// check Gpu::has_features to know if they were granted
+    pub fn request_features_if_supported(mut self, f: wgpu::Features) -> Self {
+        self.optional_features |= f;
+        self
+    }
+
     pub fn with_limits(mut self, l: wgpu::Limits) -> Self {
-        self.limits = l;
+        self.limits = GpuLimits::Custom(l);
+        self
+    }
+
+    /// request the best limits supported by the adapter
+    pub fn with_adapter_limits(mut self) -> Self {
+        self.limits = GpuLimits::Adapter;
+        self
+    }
+
+    /// request limits supported by most of the downlevel (GL, DX11) adapters
+    pub fn with_downlevel_limits(mut self) -> Self {
+        self.limits = GpuLimits::Custom(wgpu::Limits::downlevel_defaults());
+        self
+    }
+
+    /// request limits supported by WebGL2
+    pub fn with_downlevel_webgl2_limits(mut self) -> Self {
+        self.limits = GpuLimits::Custom(wgpu::Limits::downlevel_webgl2_defaults());
         self
     }
 
@@ -39,6 +225,45 @@ impl GpuBuilder {
         self
     }
 
+    /// add a surface format to the ranked list of preferred formats
+    /// the first supported one is used, by default the first sRGB format is picked
+    pub fn with_surface_format(mut self, f: wgpu::TextureFormat) -> Self {
+        self.surface_formats.push(f);
+        self
+    }
+
+    /// add a format that views of the surface textures can be created with
+    /// it can only differ from the surface format by its sRGB-ness
+    pub fn with_view_format(mut self, f: wgpu::TextureFormat) -> Self {
+        self.view_formats.push(f);
+        self
+    }
+
+    /// set the alpha mode of the surface, use it for transparent windows
+    pub fn with_alpha_mode(mut self, m: wgpu::CompositeAlphaMode) -> Self {
+        self.alpha_mode = Some(m);
+        self
+    }
+
+    /// add usages to the surface textures (always usable as render attachment)
+    pub fn with_surface_usages(mut self, u: wgpu::TextureUsages) -> Self {
+        self.surface_usages |= u;
+        self
+    }
+
+    /// set what to do with the uncaptured device errors
+    pub fn with_error_policy(mut self, p: ErrorPolicy) -> Self {
+        self.error_policy = p;
+        self
+    }
+
+    /// record a wgpu api trace in a timestamped directory inside dir
+    /// needs the trace feature
+    pub fn with_trace_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.trace_dir = Some(dir.into());
+        self
+    }
+
     fn select_adapter(
         &self,
         instance: &wgpu::Instance,
@@ -49,7 +274,7 @@ impl GpuBuilder {
             let info = a.get_info();
             if info.device_type == self.device_type
                 && self.backends.contains(wgpu::Backends::from(info.backend))
-                && a.is_surface_supported(&surface)
+                && a.is_surface_supported(surface)
             {
                 return Some(a);
             }
@@ -62,56 +287,209 @@ impl GpuBuilder {
         None
     }
 
-    pub async fn build(&self, window: &winit::window::Window) -> Gpu {
+    fn select_features(&self, adapter: &wgpu::Adapter) -> Result<wgpu::Features, GpuError> {
+        let supported = adapter.features();
+        if !supported.contains(self.features) {
+            return Err(GpuError::MissingFeatures(self.features - supported));
+        }
+
+        let missing = self.optional_features - supported;
+        if !missing.is_empty() {
+            info!("optional features not supported : {:?}", missing);
+        }
+
+        Ok(self.features | (self.optional_features & supported))
+    }
+
+    fn select_limits(&self, adapter: &wgpu::Adapter) -> Result<wgpu::Limits, GpuError> {
+        let supported = adapter.limits();
+        match &self.limits {
+            GpuLimits::Adapter => Ok(supported),
+            GpuLimits::Custom(limits) => {
+                let mut failed = vec![];
+                limits.check_limits_with_fail_fn(&supported, false, |name, requested, allowed| {
+                    failed.push((name, requested, allowed))
+                });
+                if failed.is_empty() {
+                    Ok(limits.clone())
+                } else {
+                    Err(GpuError::UnsupportedLimits(failed))
+                }
+            }
+        }
+    }
+
+    /// timestamped trace directory inside dir, None when the trace feature is disabled
+    #[cfg(feature = "trace")]
+    fn create_trace_dir(dir: &Path) -> Result<Option<PathBuf>, GpuError> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = dir.join(format!("trace-{timestamp}"));
+        std::fs::create_dir_all(&path).map_err(GpuError::TraceDir)?;
+        info!("recording wgpu api trace in : {}", path.display());
+        Ok(Some(path))
+    }
+
+    #[cfg(not(feature = "trace"))]
+    fn create_trace_dir(dir: &Path) -> Result<Option<PathBuf>, GpuError> {
+        log::warn!(
+            "wgpu api trace requested in {} but the trace feature is disabled",
+            dir.display()
+        );
+        Ok(None)
+    }
+
+    fn select_surface_config(
+        &self,
+        adapter: &wgpu::Adapter,
+        surface: &wgpu::Surface,
+        size: (u32, u32),
+    ) -> Result<wgpu::SurfaceConfiguration, GpuError> {
+        let surface_caps = surface.get_capabilities(adapter);
+        if surface_caps.formats.is_empty() {
+            return Err(GpuError::NoSurfaceFormat);
+        }
+
+        let format = if self.surface_formats.is_empty() {
+            surface_caps
+                .formats
+                .iter()
+                .copied()
+                .find(|f| f.is_srgb())
+                .unwrap_or(surface_caps.formats[0])
+        } else {
+            self.surface_formats
+                .iter()
+                .copied()
+                .find(|f| surface_caps.formats.contains(f))
+                .ok_or_else(|| GpuError::UnsupportedFormat {
+                    preferred: self.surface_formats.clone(),
+                    supported: surface_caps.formats.clone(),
+                })?
+        };
+
+        let view_formats_supported = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS);
+        for &view_format in &self.view_formats {
+            if view_format != format
+                && (!view_formats_supported
+                    || view_format.remove_srgb_suffix() != format.remove_srgb_suffix())
+            {
+                return Err(GpuError::UnsupportedViewFormat {
+                    format,
+                    view_format,
+                });
+            }
+        }
+
+        let alpha_mode = match self.alpha_mode {
+            Some(m) if surface_caps.alpha_modes.contains(&m) => m,
+            Some(m) => {
+                return Err(GpuError::UnsupportedAlphaMode {
+                    requested: m,
+                    supported: surface_caps.alpha_modes,
+                })
+            }
+            None => surface_caps.alpha_modes[0],
+        };
+
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | self.surface_usages;
+        if !surface_caps.usages.contains(usage) {
+            return Err(GpuError::UnsupportedSurfaceUsages {
+                requested: usage,
+                supported: surface_caps.usages,
+            });
+        }
+
+        Ok(wgpu::SurfaceConfiguration {
+            format,
+            width: size.0,
+            height: size.1,
+            present_mode: self.present_mode,
+            usage,
+            alpha_mode,
+            view_formats: self.view_formats.clone(),
+        })
+    }
+
+    pub async fn build(&self, window: &winit::window::Window) -> Result<Gpu, GpuError> {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: self.backends,
             dx12_shader_compiler: Default::default(),
             flags: wgpu::InstanceFlags::debugging(),
             ..Default::default()
         });
-        let surface = unsafe { instance.create_surface(window).unwrap() };
-        let adapter = self.select_adapter(&instance, &surface).unwrap();
+        let surface =
+            unsafe { instance.create_surface(window) }.map_err(GpuError::CreateSurface)?;
+        let adapter = self
+            .select_adapter(&instance, &surface)
+            .ok_or(GpuError::NoAdapter {
+                backends: self.backends,
+                device_type: self.device_type,
+            })?;
+        let features = self.select_features(&adapter)?;
+        let limits = self.select_limits(&adapter)?;
+        let trace_path = match &self.trace_dir {
+            Some(dir) => Self::create_trace_dir(dir)?,
+            None => None,
+        };
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: self.features,
-                    limits: self.limits.clone(),
+                    features,
+                    limits,
                     label: Some("default device"),
                 },
-                None,
+                trace_path.as_deref(),
             )
             .await
-            .unwrap();
+            .map_err(GpuError::RequestDevice)?;
 
         info!("running on : {}", adapter.get_info().name);
 
-        let window_size = window.inner_size();
+        let errors = Arc::new(Mutex::new(VecDeque::new()));
+        let policy = self.error_policy;
+        let collected = errors.clone();
+        device.on_uncaptured_error(Box::new(move |e| match policy {
+            ErrorPolicy::Panic => panic!("wgpu error: {e}"),
+            ErrorPolicy::Log => error!(target: "wgpu_error", "{e}"),
+            ErrorPolicy::Collect => {
+                error!(target: "wgpu_error", "{e}");
+                let mut collected = collected.lock().unwrap();
+                if collected.len() >= MAX_COLLECTED_ERRORS {
+                    collected.pop_front();
+                }
+                collected.push_back(e.to_string());
+            }
+        }));
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surace_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-
-        let surface_config = wgpu::SurfaceConfiguration {
-            format: surace_format,
-            width: window_size.width,
-            height: window_size.height,
-            present_mode: self.present_mode,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
+        let window_size = window.inner_size();
+        let surface_config = self.select_surface_config(
+            &adapter,
+            &surface,
+            (window_size.width, window_size.height),
+        )?;
         surface.configure(&device, &surface_config);
 
-        Gpu {
+        info!(
+            "surface format : {:?}, alpha mode : {:?}",
+            surface_config.format, surface_config.alpha_mode
+        );
+
+        Ok(Gpu {
             device,
             queue,
             surface,
             surface_config,
-        }
+            scene_format: None,
+            render_size: RenderTargetSize::default(),
+            blitter: OnceLock::new(),
+            errors,
+        })
     }
 }
 
@@ -122,7 +500,14 @@ impl Default for GpuBuilder {
             device_type: wgpu::DeviceType::DiscreteGpu,
             present_mode: wgpu::PresentMode::Fifo,
             features: wgpu::Features::empty(),
-            limits: wgpu::Limits::default(),
+            optional_features: wgpu::Features::empty(),
+            limits: GpuLimits::Custom(wgpu::Limits::default()),
+            surface_formats: vec![],
+            view_formats: vec![],
+            alpha_mode: None,
+            surface_usages: wgpu::TextureUsages::empty(),
+            error_policy: ErrorPolicy::Panic,
+            trace_dir: None,
         }
     }
 }
@@ -133,6 +518,13 @@ pub struct Gpu {
     pub queue: wgpu::Queue,
     pub surface: wgpu::Surface,
     pub(crate) surface_config: wgpu::SurfaceConfiguration,
+    /// format of the scene target when it is not the surface
+    pub(crate) scene_format: Option<wgpu::TextureFormat>,
+    /// size of the scene target relative to the surface
+    pub(crate) render_size: RenderTargetSize,
+    /// pipelines of Gpu::blit
+    blitter: OnceLock<Blitter>,
+    errors: Arc<Mutex<VecDeque<String>>>,
 }
 
 impl Gpu {
@@ -142,7 +534,119 @@ impl Gpu {
         self.surface.configure(&self.device, &self.surface_config);
     }
 
+    /// size of the surface in pixels
+    ///
+    /// the frame given to AppInstance::render has the scene size, see Gpu::scene_size
+    pub fn surface_size(&self) -> (u32, u32) {
+        (self.surface_config.width, self.surface_config.height)
+    }
+
+    /// size of the scene target, the surface size unless the app has an internal resolution
+    ///
+    /// the frame view given to AppInstance::render has this size, as must the attachments
+    /// used with it
+    pub fn scene_size(&self) -> (u32, u32) {
+        self.render_size
+            .resolve(self.surface_size(), self.max_texture_size())
+    }
+
+    /// largest width or height of a 2d texture on the device
+    pub fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// width / height ratio of the scene target
+    pub fn aspect_ratio(&self) -> f32 {
+        let (width, height) = self.scene_size();
+        width as f32 / height.max(1) as f32
+    }
+
     pub fn get_surface_texture_format(&self) -> wgpu::TextureFormat {
         self.surface_config.format
     }
+
+    /// format of the frame view given to AppInstance::render, Rgba16Float when the app is hdr
+    pub fn get_scene_format(&self) -> wgpu::TextureFormat {
+        self.scene_format.unwrap_or(self.surface_config.format)
+    }
+
+    /// draw src to dst_view, a view of the scene target given to AppInstance::render
+    pub fn blit(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        src: &Texture,
+        dst_view: &wgpu::TextureView,
+        mode: BlitMode,
+    ) {
+        self.blit_to(
+            encoder,
+            src,
+            dst_view,
+            self.get_scene_format(),
+            self.scene_size(),
+            mode,
+        );
+    }
+
+    /// draw src to any view of the given format and size
+    pub fn blit_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        src: &Texture,
+        dst_view: &wgpu::TextureView,
+        dst_format: wgpu::TextureFormat,
+        dst_size: (u32, u32),
+        mode: BlitMode,
+    ) {
+        self.blitter
+            .get_or_init(|| Blitter::new(&self.device))
+            .blit(
+                &self.device,
+                encoder,
+                src,
+                dst_view,
+                dst_format,
+                dst_size,
+                mode,
+            );
+    }
+
+    /// run f inside a validation error scope
+    /// the validation errors are returned instead of being sent to the error policy
+    pub fn validate<T>(&self, f: impl FnOnce(&Gpu) -> T) -> Result<T, wgpu::Error> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let value = f(self);
+        match block_on(self.device.pop_error_scope()) {
+            Some(e) => Err(e),
+            None => Ok(value),
+        }
+    }
+
+    /// take the errors collected with ErrorPolicy::Collect, oldest first
+    pub fn take_errors(&self) -> Vec<String> {
+        self.errors.lock().unwrap().drain(..).collect()
+    }
+
+    /// features enabled on the device
+    pub fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// true if all the features are enabled on the device
+    pub fn has_features(&self, f: wgpu::Features) -> bool {
+        self.device.features().contains(f)
+    }
+
+    /// limits of the device
+    pub fn limits(&self) -> wgpu::Limits {
+        self.device.limits()
+    }
+
+    pub fn get_surface_view_formats(&self) -> &[wgpu::TextureFormat] {
+        &self.surface_config.view_formats
+    }
+
+    pub fn get_surface_usages(&self) -> wgpu::TextureUsages {
+        self.surface_config.usage
+    }
 }
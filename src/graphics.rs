@@ -1,31 +1,12 @@
+use crate::layout::VertexLayout;
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
 pub struct Vertex2D {
     pub pos: [f32; 2],
     pub uv: [f32; 2],
 }
 
-impl Vertex2D {
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    shader_location: 0,
-                    offset: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    shader_location: 1,
-                    offset: 8,
-                },
-            ],
-        }
-    }
-}
-
 pub fn vertex2d(pos: impl Into<[f32; 2]>, uv: impl Into<[f32; 2]>) -> Vertex2D {
     Vertex2D {
         pos: pos.into(),
@@ -46,40 +27,41 @@ pub const QUAD: &[Vertex2D] = &[
 
 pub const QUAD_INDICES: &[u32] = &[0, 1, 3, 1, 2, 3];
 
+/// axis aligned rectangle, (x, y) is its top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub const fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn min(&self) -> [f32; 2] {
+        [self.x, self.y]
+    }
+
+    pub fn max(&self) -> [f32; 2] {
+        [self.x + self.w, self.y + self.h]
+    }
+
+    pub fn contains(&self, p: [f32; 2]) -> bool {
+        p[0] >= self.x && p[0] < self.x + self.w && p[1] >= self.y && p[1] < self.y + self.h
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
 pub struct Vertex {
     pub pos: [f32; 4],
     pub normal: [f32; 3],
     pub uv: [f32; 2],
 }
 
-impl Vertex {
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    shader_location: 0,
-                    offset: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    shader_location: 1,
-                    offset: 16,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    shader_location: 2,
-                    offset: 28,
-                },
-            ],
-        }
-    }
-}
-
 pub fn vertex(
     pos: impl Into<[f32; 4]>,
     normal: impl Into<[f32; 3]>,
@@ -92,6 +74,31 @@ pub fn vertex(
     }
 }
 
+/// vertex of a skinned mesh, Vertex extended with 4 joint indices and weights
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct SkinnedVertex {
+    pub pos: [f32; 4],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+pub fn skinned_vertex(
+    v: Vertex,
+    joints: impl Into<[u32; 4]>,
+    weights: impl Into<[f32; 4]>,
+) -> SkinnedVertex {
+    SkinnedVertex {
+        pos: v.pos,
+        normal: v.normal,
+        uv: v.uv,
+        joints: joints.into(),
+        weights: weights.into(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TextureBuilder<'a> {
     data: &'a [u8],
@@ -100,6 +107,9 @@ pub struct TextureBuilder<'a> {
     address_mode: wgpu::AddressMode,
     min_filter: wgpu::FilterMode,
     mag_filter: wgpu::FilterMode,
+    mip_level_count: u32,
+    cube: bool,
+    depth: u32,
     texture_desc: Option<wgpu::TextureDescriptor<'a>>,
     sampler_desc: Option<wgpu::SamplerDescriptor<'a>>,
 }
@@ -112,6 +122,9 @@ impl<'a> Default for TextureBuilder<'a> {
             address_mode: wgpu::AddressMode::ClampToEdge,
             min_filter: wgpu::FilterMode::Nearest,
             mag_filter: wgpu::FilterMode::Linear,
+            mip_level_count: 1,
+            cube: false,
+            depth: 1,
             texture_desc: None,
             sampler_desc: None,
             data: &[],
@@ -149,6 +162,24 @@ impl<'a> TextureBuilder<'a> {
         self
     }
 
+    /// number of mip levels, only the first one is filled with the data
+    pub fn with_mip_levels(mut self, count: u32) -> Self {
+        self.mip_level_count = count.max(1);
+        self
+    }
+
+    /// cubemap of 6 square layers (+x, -x, +y, -y, +z, -z), the data holds the faces in order
+    pub fn with_cube(mut self) -> Self {
+        self.cube = true;
+        self
+    }
+
+    /// 3d texture of `depth` slices, the data holds the slices in order
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
     pub fn with_texture_desc(mut self, td: wgpu::TextureDescriptor<'a>) -> Self {
         self.texture_desc = Some(td);
         self
@@ -168,33 +199,45 @@ impl<'a> TextureBuilder<'a> {
         let size = wgpu::Extent3d {
             width: dim.0,
             height: dim.1,
-            depth_or_array_layers: 1,
+            depth_or_array_layers: if self.cube { 6 } else { self.depth },
         };
+        let volume = !self.cube && self.depth > 1;
 
         let wgpu_texture = match &self.texture_desc {
-            Some(desc) => device.create_texture(&desc),
+            Some(desc) => device.create_texture(desc),
             None => device.create_texture(&wgpu::TextureDescriptor {
                 label: None,
                 format: self.format,
-                dimension: wgpu::TextureDimension::D2,
+                dimension: match volume {
+                    true => wgpu::TextureDimension::D3,
+                    false => wgpu::TextureDimension::D2,
+                },
                 usage: self.usages,
-                mip_level_count: 1,
+                mip_level_count: self.mip_level_count,
                 sample_count: 1,
                 size,
                 view_formats: &[],
             }),
         };
 
-        let view = wgpu_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = wgpu_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: match (self.cube, volume) {
+                (true, _) => Some(wgpu::TextureViewDimension::Cube),
+                (_, true) => Some(wgpu::TextureViewDimension::D3),
+                _ => None,
+            },
+            ..Default::default()
+        });
 
         let sampler = match &self.sampler_desc {
-            Some(desc) => device.create_sampler(&desc),
+            Some(desc) => device.create_sampler(desc),
             None => device.create_sampler(&wgpu::SamplerDescriptor {
                 address_mode_u: self.address_mode,
                 address_mode_v: self.address_mode,
                 address_mode_w: self.address_mode,
                 min_filter: self.min_filter,
                 mag_filter: self.mag_filter,
+                mipmap_filter: self.min_filter,
                 ..Default::default()
             }),
         };
@@ -203,7 +246,8 @@ impl<'a> TextureBuilder<'a> {
             sampler,
             view,
             size,
-            texel_size: self.format.block_size(None).unwrap(),
+            // depth formats such as Depth24Plus have no texel size and are never uploaded to
+            texel_size: self.format.block_size(None).unwrap_or(0),
         };
         texture.upload_data(self.data, queue);
 
@@ -222,7 +266,7 @@ pub struct Texture {
 
 impl Texture {
     pub fn upload_data(&self, data: &[u8], queue: &wgpu::Queue) {
-        if data.len() != 0 {
+        if !data.is_empty() {
             queue.write_texture(
                 wgpu::ImageCopyTextureBase {
                     texture: &self.texture,
@@ -233,11 +277,47 @@ impl Texture {
                 data,
                 wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(self.size.width * self.texel_size as u32),
+                    bytes_per_row: Some(self.size.width * self.texel_size),
                     rows_per_image: Some(self.size.height),
                 },
                 self.size,
             );
         }
     }
+
+    /// write size.0 x size.1 tightly packed texels at origin
+    pub fn upload_region(
+        &self,
+        origin: (u32, u32),
+        size: (u32, u32),
+        data: &[u8],
+        queue: &wgpu::Queue,
+    ) {
+        if data.is_empty() {
+            return;
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTextureBase {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.0 * self.texel_size),
+                rows_per_image: Some(size.1),
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
 }
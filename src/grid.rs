@@ -0,0 +1,330 @@
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use crate::camera::CameraUniform;
+use crate::gpu::Gpu;
+use crate::layout::GpuUniform;
+
+const GRID_WGSL: &str = r#"
+struct Grid {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    line_color: vec4<f32>,
+    major_color: vec4<f32>,
+    x_axis_color: vec4<f32>,
+    z_axis_color: vec4<f32>,
+    spacing: f32,
+    major_every: f32,
+    fade_distance: f32,
+    line_width: f32,
+}
+
+@group(0) @binding(0) var<uniform> grid: Grid;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+fn unproject(ndc: vec2<f32>, z: f32) -> vec3<f32> {
+    let p = grid.inv_view_proj * vec4<f32>(ndc, z, 1.0);
+    return p.xyz / p.w;
+}
+
+// coverage of the lines every `spacing` units, anti-aliased over one pixel
+fn lines(coord: vec2<f32>, width: f32) -> f32 {
+    let derivative = fwidth(coord);
+    let distance = abs(fract(coord - 0.5) - 0.5) / derivative;
+    return 1.0 - clamp(min(distance.x, distance.y) - width * 0.5 + 0.5, 0.0, 1.0);
+}
+
+fn axis(coord: f32, width: f32) -> f32 {
+    return 1.0 - clamp(abs(coord) / fwidth(coord) - width + 0.5, 0.0, 1.0);
+}
+
+struct GridPoint {
+    color: vec4<f32>,
+    clip: vec4<f32>,
+}
+
+fn shade(ndc: vec2<f32>) -> GridPoint {
+    // intersection of the view ray with the y = 0 plane
+    let near = unproject(ndc, 0.0);
+    let far = unproject(ndc, 1.0);
+    let t = -near.y / (far.y - near.y);
+    let p = near + t * (far - near);
+
+    let minor = lines(p.xz / grid.spacing, grid.line_width);
+    let major = lines(p.xz / (grid.spacing * grid.major_every), grid.line_width * 1.5);
+    var color = vec4<f32>(grid.line_color.rgb, grid.line_color.a * minor);
+    color = mix(color, grid.major_color, major * grid.major_color.a);
+    color = mix(color, grid.x_axis_color, axis(p.z, grid.line_width * 2.0) * grid.x_axis_color.a);
+    color = mix(color, grid.z_axis_color, axis(p.x, grid.line_width * 2.0) * grid.z_axis_color.a);
+
+    let fade = 1.0 - smoothstep(0.0, grid.fade_distance, distance(grid.eye.xz, p.xz));
+    color.a *= fade;
+    // discarded after the derivatives, which need uniform control flow
+    if t <= 0.0 || color.a <= 0.001 {
+        discard;
+    }
+
+    return GridPoint(color, grid.view_proj * vec4<f32>(p, 1.0));
+}
+
+@fragment
+fn fs_overlay(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in.ndc).color;
+}
+
+struct DepthOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> DepthOutput {
+    let point = shade(in.ndc);
+    return DepthOutput(point.color, point.clip.z / point.clip.w);
+}
+"#;
+
+/// appearance of the ground grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridConfig {
+    /// distance between two lines
+    pub spacing: f32,
+    /// number of lines between two major lines
+    pub major_every: u32,
+    pub line_color: [f32; 4],
+    pub major_color: [f32; 4],
+    /// color of the line along the x axis
+    pub x_axis_color: [f32; 4],
+    /// color of the line along the z axis
+    pub z_axis_color: [f32; 4],
+    /// distance from the camera at which the grid has faded out
+    pub fade_distance: f32,
+    /// line width in pixels
+    pub line_width: f32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            major_every: 10,
+            line_color: [0.5, 0.5, 0.5, 0.4],
+            major_color: [0.6, 0.6, 0.6, 0.8],
+            x_axis_color: [0.9, 0.2, 0.2, 1.0],
+            z_axis_color: [0.2, 0.4, 0.9, 1.0],
+            fade_distance: 100.0,
+            line_width: 1.0,
+        }
+    }
+}
+
+impl GridConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_major_every(mut self, major_every: u32) -> Self {
+        self.major_every = major_every.max(1);
+        self
+    }
+
+    pub fn with_line_color(mut self, color: [f32; 4]) -> Self {
+        self.line_color = color;
+        self
+    }
+
+    pub fn with_major_color(mut self, color: [f32; 4]) -> Self {
+        self.major_color = color;
+        self
+    }
+
+    pub fn with_axis_colors(mut self, x: [f32; 4], z: [f32; 4]) -> Self {
+        self.x_axis_color = x;
+        self.z_axis_color = z;
+        self
+    }
+
+    pub fn with_fade_distance(mut self, distance: f32) -> Self {
+        self.fade_distance = distance;
+        self
+    }
+
+    pub fn with_line_width(mut self, width: f32) -> Self {
+        self.line_width = width;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+    line_color: [f32; 4],
+    major_color: [f32; 4],
+    x_axis_color: [f32; 4],
+    z_axis_color: [f32; 4],
+    spacing: f32,
+    major_every: f32,
+    fade_distance: f32,
+    line_width: f32,
+}
+
+/// infinite grid on the y = 0 plane, drawn with a fullscreen triangle
+///
+/// with a depth format it outputs the depth of the plane and is depth tested, draw it inside the
+/// scene pass (or after it with the scene depth, as App::with_grid does) so the scene hides it
+#[derive(Debug)]
+pub struct GridRenderer {
+    pub config: GridConfig,
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GridRenderer {
+    /// grid drawn over the scene target without depth, it is never hidden by the scene
+    pub fn new(gpu: &Gpu, config: GridConfig) -> Self {
+        Self::with_formats(gpu, config, gpu.get_scene_format(), None)
+    }
+
+    /// grid for a pass with the given color and depth formats, the main way to draw it
+    pub fn with_formats(
+        gpu: &Gpu,
+        config: GridConfig,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let device = &gpu.device;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("grid_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("grid_buffer"),
+            contents: bytemuck::bytes_of(&GridUniform::new(&config, Mat4::IDENTITY, [0.0; 4])),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("grid_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("grid_shader"),
+            source: wgpu::ShaderSource::Wgsl(GRID_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("grid_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("grid_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if depth_format.is_some() {
+                    "fs_depth"
+                } else {
+                    "fs_overlay"
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            config,
+            pipeline,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// upload the camera and the config
+    pub fn prepare(&self, gpu: &Gpu, camera: &CameraUniform) {
+        let view_proj = Mat4::from_cols_array_2d(&camera.view_proj);
+        let uniform = GridUniform::new(&self.config, view_proj, camera.eye);
+        gpu.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+impl GridUniform {
+    fn new(config: &GridConfig, view_proj: Mat4, eye: [f32; 4]) -> Self {
+        Self {
+            view_proj: view_proj.to_cols_array_2d(),
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            eye,
+            line_color: config.line_color,
+            major_color: config.major_color,
+            x_axis_color: config.x_axis_color,
+            z_axis_color: config.z_axis_color,
+            spacing: config.spacing,
+            major_every: config.major_every as f32,
+            fade_distance: config.fade_distance,
+            line_width: config.line_width,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::marker::PhantomData;
+
+use glam::Mat4;
+
+use crate::layout::VertexLayout;
+
+/// per instance model matrix, the normal matrix is derived from it in the shader
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+pub struct InstanceTransform {
+    pub model: [[f32; 4]; 4],
+}
+
+impl From<Mat4> for InstanceTransform {
+    fn from(model: Mat4) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+        }
+    }
+}
+
+/// vertex buffer layout of instances, with shader locations moved after the ones of a vertex
+#[derive(Debug, Clone)]
+pub struct InstanceLayout {
+    pub array_stride: wgpu::BufferAddress,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl InstanceLayout {
+    /// layout of T starting at first_location
+    pub fn new<T: VertexLayout>(first_location: u32) -> Self {
+        let start = T::ATTRIBUTES
+            .iter()
+            .map(|a| a.shader_location)
+            .min()
+            .unwrap_or(0);
+        let attributes = T::ATTRIBUTES
+            .iter()
+            .map(|a| wgpu::VertexAttribute {
+                shader_location: a.shader_location - start + first_location,
+                ..*a
+            })
+            .collect();
+
+        Self {
+            array_stride: std::mem::size_of::<T>() as wgpu::BufferAddress,
+            attributes,
+        }
+    }
+
+    /// layout of T starting after the last shader location of V
+    pub fn after<V: VertexLayout, T: VertexLayout>() -> Self {
+        let first_location = V::ATTRIBUTES
+            .iter()
+            .map(|a| a.shader_location + 1)
+            .max()
+            .unwrap_or(0);
+        Self::new::<T>(first_location)
+    }
+
+    pub fn desc(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+/// growable vertex buffer of instances, rewritten every frame
+#[derive(Debug)]
+pub struct InstanceBuffer<T: bytemuck::Pod> {
+    pub buffer: wgpu::Buffer,
+    len: u32,
+    capacity: u64,
+    _instance: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> InstanceBuffer<T> {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) -> Self {
+        let mut buffer = Self::with_capacity(device, instances.len() as u64);
+        buffer.update(device, queue, instances);
+        buffer
+    }
+
+    pub fn with_capacity(device: &wgpu::Device, capacity: u64) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, capacity.max(1)),
+            len: 0,
+            capacity: capacity.max(1),
+            _instance: PhantomData,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_buffer"),
+            size: capacity * std::mem::size_of::<T>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// replace the instances, the buffer is reallocated when they don't fit
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) {
+        let len = instances.len() as u64;
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        self.len = instances.len() as u32;
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// number of instances the buffer can hold without reallocating
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// the part of the buffer holding the instances
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer
+            .slice(..self.len as u64 * std::mem::size_of::<T>() as u64)
+    }
+}
+
+impl<T: bytemuck::Pod + VertexLayout> InstanceBuffer<T> {
+    /// layout of T placed after the vertex attributes of V
+    pub fn layout<V: VertexLayout>() -> InstanceLayout {
+        InstanceLayout::after::<V, T>()
+    }
+}
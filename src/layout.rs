@@ -0,0 +1,131 @@
+//! layouts of the data shared with shaders, see `#[derive(VertexLayout)]` and `#[derive(GpuUniform)]`
+
+pub use wgpu_sandbox2_derive::{GpuUniform, VertexLayout};
+
+/// types usable as vertex buffers
+pub trait VertexLayout: Sized {
+    const STEP_MODE: wgpu::VertexStepMode;
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: Self::STEP_MODE,
+            attributes: Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// vertex formats of a field type, one per shader location (matrices take one per column)
+pub trait VertexAttributes {
+    const FORMATS: &'static [wgpu::VertexFormat];
+}
+
+macro_rules! vertex_attributes {
+    ($($ty:ty => [$($format:ident),+];)*) => {
+        $(impl VertexAttributes for $ty {
+            const FORMATS: &'static [wgpu::VertexFormat] = &[$(wgpu::VertexFormat::$format),+];
+        })*
+    };
+}
+
+vertex_attributes! {
+    f32 => [Float32];
+    [f32; 2] => [Float32x2];
+    [f32; 3] => [Float32x3];
+    [f32; 4] => [Float32x4];
+    u32 => [Uint32];
+    [u32; 2] => [Uint32x2];
+    [u32; 3] => [Uint32x3];
+    [u32; 4] => [Uint32x4];
+    i32 => [Sint32];
+    [i32; 2] => [Sint32x2];
+    [i32; 3] => [Sint32x3];
+    [i32; 4] => [Sint32x4];
+    [u16; 2] => [Uint16x2];
+    [u16; 4] => [Uint16x4];
+    [i16; 2] => [Sint16x2];
+    [i16; 4] => [Sint16x4];
+    [u8; 2] => [Uint8x2];
+    [u8; 4] => [Uint8x4];
+    [i8; 2] => [Sint8x2];
+    [i8; 4] => [Sint8x4];
+    [[f32; 4]; 4] => [Float32x4, Float32x4, Float32x4, Float32x4];
+    glam::Vec2 => [Float32x2];
+    glam::Vec3 => [Float32x3];
+    glam::Vec4 => [Float32x4];
+    glam::UVec2 => [Uint32x2];
+    glam::UVec3 => [Uint32x3];
+    glam::UVec4 => [Uint32x4];
+    glam::IVec2 => [Sint32x2];
+    glam::IVec3 => [Sint32x3];
+    glam::IVec4 => [Sint32x4];
+    glam::Mat2 => [Float32x2, Float32x2];
+    glam::Mat3 => [Float32x3, Float32x3, Float32x3];
+    glam::Mat4 => [Float32x4, Float32x4, Float32x4, Float32x4];
+}
+
+/// alignment and size of a type in WGSL host-shareable memory
+pub trait WgslType {
+    const ALIGN: usize;
+    const SIZE: usize;
+    const NAME: &'static str;
+    const IS_STRUCT: bool = false;
+}
+
+macro_rules! wgsl_types {
+    ($($ty:ty => ($align:literal, $size:literal, $name:literal);)*) => {
+        $(impl WgslType for $ty {
+            const ALIGN: usize = $align;
+            const SIZE: usize = $size;
+            const NAME: &'static str = $name;
+        })*
+    };
+}
+
+wgsl_types! {
+    f32 => (4, 4, "f32");
+    [f32; 2] => (8, 8, "vec2<f32>");
+    [f32; 3] => (16, 12, "vec3<f32>");
+    [f32; 4] => (16, 16, "vec4<f32>");
+    u32 => (4, 4, "u32");
+    [u32; 2] => (8, 8, "vec2<u32>");
+    [u32; 3] => (16, 12, "vec3<u32>");
+    [u32; 4] => (16, 16, "vec4<u32>");
+    i32 => (4, 4, "i32");
+    [i32; 2] => (8, 8, "vec2<i32>");
+    [i32; 3] => (16, 12, "vec3<i32>");
+    [i32; 4] => (16, 16, "vec4<i32>");
+    [[f32; 4]; 4] => (16, 64, "mat4x4<f32>");
+    glam::Vec2 => (8, 8, "vec2<f32>");
+    glam::Vec3 => (16, 12, "vec3<f32>");
+    glam::Vec4 => (16, 16, "vec4<f32>");
+    glam::UVec2 => (8, 8, "vec2<u32>");
+    glam::UVec3 => (16, 12, "vec3<u32>");
+    glam::UVec4 => (16, 16, "vec4<u32>");
+    glam::IVec2 => (8, 8, "vec2<i32>");
+    glam::IVec3 => (16, 12, "vec3<i32>");
+    glam::IVec4 => (16, 16, "vec4<i32>");
+    glam::Mat2 => (8, 16, "mat2x2<f32>");
+    glam::Mat4 => (16, 64, "mat4x4<f32>");
+}
+
+/// structs whose layout was checked against WGSL
+pub trait GpuUniform: WgslType {
+    /// name and WGSL type of each member, padding excluded
+    fn wgsl_members() -> Vec<(&'static str, String)>;
+
+    /// WGSL declaration of the struct
+    fn wgsl_struct() -> String {
+        let mut wgsl = format!("struct {} {{\n", Self::NAME);
+        for (name, ty) in Self::wgsl_members() {
+            wgsl += &format!("    {name}: {ty},\n");
+        }
+        wgsl + "}\n"
+    }
+}
+
+/// round offset up to a multiple of align
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
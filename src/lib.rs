@@ -1,6 +1,39 @@
 pub mod app;
+pub mod atlas;
+pub mod blit;
+pub mod camera;
+pub mod debug_draw;
+pub mod environment;
 pub mod gpu;
 pub mod graphics;
+pub mod grid;
+pub mod instance;
+pub mod layout;
+pub mod logging;
+pub mod material;
+pub mod mesh;
+pub mod postprocess;
+pub mod render_target;
+pub mod shadow;
+pub mod sprite;
+pub mod tonemap;
 
 #[cfg(feature = "egui")]
 pub mod egui_renderer;
+
+#[cfg(feature = "gltf")]
+pub mod animation;
+#[cfg(feature = "gltf")]
+pub mod gltf_scene;
+
+#[cfg(feature = "obj")]
+pub mod obj;
+
+#[cfg(feature = "text")]
+pub mod text;
+
+pub use glam;
+pub use wgpu;
+
+// lets the derive macros refer to this crate from inside it
+extern crate self as wgpu_sandbox2;
@@ -0,0 +1,267 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// configuration of the logger installed by the app
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    /// env_logger like filter string (ex: "info,wgpu_core=warn")
+    filter: String,
+    /// levels applied on top of the filter string
+    levels: Vec<(String, LevelFilter)>,
+    /// if true, RUST_LOG is applied on top of the filters
+    use_env: bool,
+    /// file where the records are also written
+    file: Option<PathBuf>,
+    /// number of records kept in memory, 0 to disable the capture
+    capture: usize,
+}
+
+impl LoggingConfig {
+    /// build a config with default options (wrapper to LoggingConfig::default)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// replace the default filter string
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = String::from(filter);
+        self
+    }
+
+    /// set the level of a target (ex: silence wgpu_core)
+    pub fn with_level(mut self, target: &str, level: LevelFilter) -> Self {
+        self.levels.push((String::from(target), level));
+        self
+    }
+
+    /// set if RUST_LOG should override the filters
+    pub fn with_env(mut self, value: bool) -> Self {
+        self.use_env = value;
+        self
+    }
+
+    /// also write the records in a file
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// keep the last records in memory (used by the egui log console)
+    pub fn with_capture(mut self, capacity: usize) -> Self {
+        self.capture = capacity;
+        self
+    }
+
+    /// install the logger, fails if a logger is already installed
+    /// returns the buffer of captured records if the capture is enabled
+    pub fn init(&self) -> Result<Option<LogBuffer>, SetLoggerError> {
+        let mut builder = env_logger::Builder::new();
+        builder.parse_filters(&self.filter);
+        for (target, level) in &self.levels {
+            builder.filter_module(target, *level);
+        }
+        if self.use_env {
+            if let Ok(filters) = std::env::var(env_logger::DEFAULT_FILTER_ENV) {
+                builder.parse_filters(&filters);
+            }
+        }
+        let inner = builder.build();
+
+        // a file that can't be opened is reported once the logger is installed
+        let (file, file_error) = match self.file.as_ref().map(|path| (path, File::create(path))) {
+            Some((_, Ok(f))) => (Some(Mutex::new(f)), None),
+            Some((path, Err(e))) => (None, Some((path, e))),
+            None => (None, None),
+        };
+
+        let buffer = (self.capture > 0).then(|| LogBuffer::new(self.capture));
+
+        let max_level = inner.filter();
+        log::set_boxed_logger(Box::new(SandboxLogger {
+            inner,
+            file,
+            buffer: buffer.clone(),
+        }))?;
+        log::set_max_level(max_level);
+
+        if let Some((path, e)) = file_error {
+            log::warn!("failed to open the log file {} : {e}", path.display());
+        }
+
+        Ok(buffer)
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: String::from("info,wgpu_core=warn,wgpu_hal=warn,naga=warn"),
+            levels: vec![],
+            use_env: true,
+            file: None,
+            capture: if cfg!(feature = "egui") { 512 } else { 0 },
+        }
+    }
+}
+
+/// a record kept in the log buffer
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// ring buffer of the last log records
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<LogRecord>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    /// copy of the records, oldest first
+    pub fn records(&self) -> Vec<LogRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+struct SandboxLogger {
+    inner: env_logger::Logger,
+    file: Option<Mutex<File>>,
+    buffer: Option<LogBuffer>,
+}
+
+impl Log for SandboxLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+
+        if let Some(file) = &self.file {
+            let _ = writeln!(
+                file.lock().unwrap(),
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+
+        if let Some(buffer) = &self.buffer {
+            buffer.push(LogRecord {
+                level: record.level(),
+                target: String::from(record.target()),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// egui window displaying the captured log records
+#[cfg(feature = "egui")]
+pub struct LogConsole {
+    buffer: LogBuffer,
+    level: LevelFilter,
+    target: String,
+    pub open: bool,
+}
+
+#[cfg(feature = "egui")]
+impl LogConsole {
+    pub fn new(buffer: LogBuffer) -> Self {
+        Self {
+            buffer,
+            level: LevelFilter::Trace,
+            target: String::new(),
+            open: false,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Log console")
+            .open(&mut open)
+            .default_size([500.0, 300.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("level")
+                        .selected_text(self.level.as_str())
+                        .show_ui(ui, |ui| {
+                            for level in LevelFilter::iter() {
+                                ui.selectable_value(&mut self.level, level, level.as_str());
+                            }
+                        });
+                    ui.label("target");
+                    ui.text_edit_singleline(&mut self.target);
+                    if ui.button("clear").clicked() {
+                        self.buffer.clear();
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for record in self.buffer.records() {
+                            if record.level > self.level || !record.target.contains(&self.target) {
+                                continue;
+                            }
+                            let color = match record.level {
+                                log::Level::Error => egui::Color32::LIGHT_RED,
+                                log::Level::Warn => egui::Color32::YELLOW,
+                                log::Level::Info => egui::Color32::LIGHT_GREEN,
+                                _ => egui::Color32::GRAY,
+                            };
+                            ui.colored_label(
+                                color,
+                                format!("[{} {}] {}", record.level, record.target, record.message),
+                            );
+                        }
+                    });
+            });
+        self.open = open;
+    }
+}
@@ -0,0 +1,977 @@
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::environment::{Environment, IBL_WGSL};
+use crate::gpu::Gpu;
+use crate::graphics::{Texture, TextureBuilder, Vertex};
+use crate::instance::{InstanceBuffer, InstanceLayout, InstanceTransform};
+use crate::layout::GpuUniform;
+use crate::mesh::Mesh;
+use crate::shadow::{ShadowConfig, ShadowMaps, SHADOW_WGSL};
+
+/// WGSL library of the lit shading: light types, light sampling, Blinn-Phong and PBR BRDFs
+pub const LIGHTING_WGSL: &str = r#"
+const PI: f32 = 3.14159265359;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct Light {
+    position: vec3<f32>,
+    range: f32,
+    direction: vec3<f32>,
+    kind: u32,
+    color: vec3<f32>,
+    intensity: f32,
+    cos_inner: f32,
+    cos_outer: f32,
+    // first shadow map layer, -1 without shadow
+    shadow_layer: i32,
+}
+
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light>,
+}
+
+// direction towards the light and radiance reaching a point
+struct LightSample {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+fn sample_light(light: Light, position: vec3<f32>) -> LightSample {
+    var sample: LightSample;
+    if light.kind == LIGHT_DIRECTIONAL {
+        sample.direction = -normalize(light.direction);
+        sample.radiance = light.color * light.intensity;
+        return sample;
+    }
+
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    sample.direction = to_light / max(distance, 0.0001);
+    // inverse square falloff, windowed to reach zero at the range
+    let window = clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0);
+    var attenuation = window * window / max(distance * distance, 0.0001);
+    if light.kind == LIGHT_SPOT {
+        let cos_angle = dot(-sample.direction, normalize(light.direction));
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+    }
+    sample.radiance = light.color * light.intensity * attenuation;
+    return sample;
+}
+
+fn blinn_phong(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    l: vec3<f32>,
+    radiance: vec3<f32>,
+    diffuse: vec3<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+) -> vec3<f32> {
+    let n_dot_l = max(dot(n, l), 0.0);
+    let h = normalize(l + v);
+    let spec = pow(max(dot(n, h), 0.0), shininess) * step(0.0001, n_dot_l);
+    return (diffuse * n_dot_l + specular * spec) * radiance;
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    let gv = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let gl = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return gv * gl;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// metallic-roughness Cook-Torrance BRDF times the incoming radiance
+fn pbr(
+    n: vec3<f32>,
+    v: vec3<f32>,
+    l: vec3<f32>,
+    radiance: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let kd = (1.0 - f) * (1.0 - metallic);
+    return (kd * base_color / PI + specular) * radiance * n_dot_l;
+}
+
+// normal mapping with a cotangent frame built from screen space derivatives (no vertex tangents)
+fn perturb_normal(
+    n: vec3<f32>,
+    position: vec3<f32>,
+    uv: vec2<f32>,
+    tangent_normal: vec3<f32>,
+) -> vec3<f32> {
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+    let dp2_perp = cross(dp2, n);
+    let dp1_perp = cross(n, dp1);
+    let t = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    let b = dp2_perp * duv1.y + dp1_perp * duv2.y;
+    let inv_max = inverseSqrt(max(max(dot(t, t), dot(b, b)), 0.0000001));
+    return normalize(mat3x3<f32>(t * inv_max, b * inv_max, n) * tangent_normal);
+}
+"#;
+
+// light_shadow when the LightBuffer has no shadow maps
+const NO_SHADOW_WGSL: &str = r#"
+fn light_shadow(light: Light, position: vec3<f32>, normal: vec3<f32>, view_depth: f32) -> f32 {
+    return 1.0;
+}
+"#;
+
+// ambient_light when the LightBuffer has no environment
+const NO_IBL_WGSL: &str = r#"
+fn ambient_light(
+    ambient: vec3<f32>,
+    n: vec3<f32>,
+    v: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    return ambient * base_color;
+}
+"#;
+
+const MATERIAL_WGSL: &str = r#"
+struct Camera {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+}
+
+const MODEL_UNLIT: u32 = 0u;
+const MODEL_BLINN_PHONG: u32 = 1u;
+const MODEL_PBR: u32 = 2u;
+
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    shininess: f32,
+    specular: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    model: u32,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<storage, read> lights: Lights;
+@group(2) @binding(0) var<uniform> material: Material;
+@group(2) @binding(1) var base_color_texture: texture_2d<f32>;
+@group(2) @binding(2) var normal_texture: texture_2d<f32>;
+@group(2) @binding(3) var orm_texture: texture_2d<f32>;
+@group(2) @binding(4) var emissive_texture: texture_2d<f32>;
+@group(2) @binding(5) var material_sampler: sampler;
+
+struct VertexInput {
+    @location(0) pos: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world = model * vec4<f32>(vertex.pos.xyz, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world;
+    out.world_position = world.xyz;
+    // inverse transpose of the model matrix, up to its determinant, from the cofactors
+    let m = mat3x3<f32>(model[0].xyz, model[1].xyz, model[2].xyz);
+    let cofactors = mat3x3<f32>(cross(m[1], m[2]), cross(m[2], m[0]), cross(m[0], m[1]));
+    let det_sign = select(1.0, -1.0, dot(m[0], cofactors[0]) < 0.0);
+    out.normal = cofactors * vertex.normal * det_sign;
+    out.uv = vertex.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color = material.base_color * textureSample(base_color_texture, material_sampler, in.uv);
+    let orm = textureSample(orm_texture, material_sampler, in.uv).rgb;
+    let emissive = material.emissive * textureSample(emissive_texture, material_sampler, in.uv).rgb;
+    var tangent_normal = textureSample(normal_texture, material_sampler, in.uv).xyz * 2.0 - 1.0;
+    tangent_normal = normalize(vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z));
+    let n = perturb_normal(normalize(in.normal), in.world_position, in.uv, tangent_normal);
+
+    if material.model == MODEL_UNLIT {
+        return vec4<f32>(base_color.rgb + emissive, base_color.a);
+    }
+
+    let v = normalize(camera.eye.xyz - in.world_position);
+    let view_depth = -(camera.view * vec4<f32>(in.world_position, 1.0)).z;
+    let geometric_normal = normalize(in.normal);
+    let occlusion = mix(1.0, orm.r, material.occlusion_strength);
+    let roughness = clamp(material.roughness * orm.g, 0.04, 1.0);
+    let metallic = material.metallic * orm.b;
+
+    var color = ambient_light(lights.ambient, n, v, base_color.rgb, metallic, roughness) * occlusion;
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        let sample = sample_light(light, in.world_position);
+        let radiance = sample.radiance * light_shadow(light, in.world_position, geometric_normal, view_depth);
+        if material.model == MODEL_BLINN_PHONG {
+            color += blinn_phong(n, v, sample.direction, radiance, base_color.rgb, material.specular, material.shininess);
+        } else {
+            color += pbr(n, v, sample.direction, radiance, base_color.rgb, metallic, roughness);
+        }
+    }
+    return vec4<f32>(color + emissive, base_color.a);
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// direction the light travels in
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// render cascaded shadow maps, when the LightBuffer has shadows
+    pub cast_shadows: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// distance at which the light has no effect
+    pub range: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    /// angle from the axis where the light starts fading, in radians
+    pub inner_angle: f32,
+    /// angle from the axis where the light is off, in radians
+    pub outer_angle: f32,
+    /// render a shadow map, when the LightBuffer has shadows
+    pub cast_shadows: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+/// light as stored in the light storage buffer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+#[uniform(storage)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    pub range: f32,
+    pub direction: [f32; 3],
+    /// 0 directional, 1 point, 2 spot
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    /// first layer of the shadow maps, -1 without shadow
+    pub shadow_layer: i32,
+    pub _pad: f32,
+}
+
+impl From<&Light> for GpuLight {
+    fn from(light: &Light) -> Self {
+        let mut gpu = <GpuLight as bytemuck::Zeroable>::zeroed();
+        gpu.shadow_layer = -1;
+        match *light {
+            Light::Directional(l) => {
+                gpu.direction = l.direction.to_array();
+                gpu.color = l.color.to_array();
+                gpu.intensity = l.intensity;
+                gpu.kind = 0;
+            }
+            Light::Point(l) => {
+                gpu.position = l.position.to_array();
+                gpu.color = l.color.to_array();
+                gpu.intensity = l.intensity;
+                gpu.range = l.range;
+                gpu.kind = 1;
+            }
+            Light::Spot(l) => {
+                gpu.position = l.position.to_array();
+                gpu.direction = l.direction.to_array();
+                gpu.color = l.color.to_array();
+                gpu.intensity = l.intensity;
+                gpu.range = l.range;
+                gpu.cos_inner = l.inner_angle.cos();
+                gpu.cos_outer = l.outer_angle.cos();
+                gpu.kind = 2;
+            }
+        }
+        gpu
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsHeader {
+    ambient: [f32; 3],
+    count: u32,
+}
+
+/// storage buffer of lights, `Lights` in LIGHTING_WGSL
+///
+/// the shadow maps (see SHADOW_WGSL) and the environment maps (see IBL_WGSL) are bound next
+/// to the lights
+#[derive(Debug)]
+pub struct LightBuffer {
+    /// ambient light, replaced by the environment when there is one
+    pub ambient: Vec3,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub shadows: Option<ShadowMaps>,
+    pub environment: Option<Environment>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    capacity: u64,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self::build(device, None, None)
+    }
+
+    /// lights with shadow maps for the lights with `cast_shadows`
+    pub fn with_shadows(gpu: &Gpu, config: ShadowConfig) -> Self {
+        Self::build(&gpu.device, Some(ShadowMaps::new(gpu, config)), None)
+    }
+
+    /// light the materials with an environment, create the pipelines afterwards
+    pub fn with_environment(self, device: &wgpu::Device, environment: Environment) -> Self {
+        Self {
+            ambient: self.ambient,
+            ..Self::build(device, self.shadows, Some(environment))
+        }
+    }
+
+    fn build(
+        device: &wgpu::Device,
+        shadows: Option<ShadowMaps>,
+        environment: Option<Environment>,
+    ) -> Self {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        if shadows.is_some() {
+            entries.extend(ShadowMaps::layout_entries(1));
+        }
+        if environment.is_some() {
+            entries.extend(Environment::layout_entries(4));
+        }
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lights_bind_group_layout"),
+            entries: &entries,
+        });
+        let capacity = 16;
+        let (buffer, bind_group) = Self::create_buffer(
+            device,
+            &bind_group_layout,
+            capacity,
+            shadows.as_ref(),
+            environment.as_ref(),
+        );
+
+        Self {
+            ambient: Vec3::splat(0.03),
+            bind_group_layout,
+            shadows,
+            environment,
+            buffer,
+            bind_group,
+            capacity,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        capacity: u64,
+        shadows: Option<&ShadowMaps>,
+        environment: Option<&Environment>,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lights_buffer"),
+            size: std::mem::size_of::<LightsHeader>() as u64
+                + capacity * std::mem::size_of::<GpuLight>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }];
+        if let Some(shadows) = shadows {
+            entries.extend(shadows.bind_group_entries(1));
+        }
+        if let Some(environment) = environment {
+            entries.extend(environment.bind_group_entries(4));
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lights_bind_group"),
+            layout,
+            entries: &entries,
+        });
+        (buffer, bind_group)
+    }
+
+    /// replace the lights, the bind group changes when the buffer grows
+    ///
+    /// with shadows, this also assigns the shadow map layers of the lights
+    pub fn update(&mut self, gpu: &Gpu, lights: &[Light]) {
+        let len = lights.len() as u64;
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            (self.buffer, self.bind_group) = Self::create_buffer(
+                &gpu.device,
+                &self.bind_group_layout,
+                self.capacity,
+                self.shadows.as_ref(),
+                self.environment.as_ref(),
+            );
+        }
+
+        let header = LightsHeader {
+            ambient: self.ambient.to_array(),
+            count: lights.len() as u32,
+        };
+        let mut gpu_lights: Vec<GpuLight> = lights.iter().map(GpuLight::from).collect();
+        if let Some(shadows) = &mut self.shadows {
+            for (light, layer) in gpu_lights.iter_mut().zip(shadows.assign(lights)) {
+                light.shadow_layer = layer.map_or(-1, |layer| layer as i32);
+            }
+        }
+        let mut data = bytemuck::bytes_of(&header).to_vec();
+        data.extend_from_slice(bytemuck::cast_slice(&gpu_lights));
+        gpu.queue.write_buffer(&self.buffer, 0, &data);
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadingModel {
+    Unlit,
+    BlinnPhong,
+    /// metallic-roughness
+    #[default]
+    Pbr,
+}
+
+/// texture inputs of a material
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSlot {
+    BaseColor,
+    Normal,
+    /// occlusion in red, roughness in green and metallic in blue
+    Orm,
+    Emissive,
+}
+
+impl TextureSlot {
+    /// TextureBuilder set up for the slot, color textures are srgb and the others linear
+    pub fn builder<'a>(self) -> TextureBuilder<'a> {
+        let format = match self {
+            TextureSlot::BaseColor | TextureSlot::Emissive => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureSlot::Normal | TextureSlot::Orm => wgpu::TextureFormat::Rgba8Unorm,
+        };
+        TextureBuilder::new()
+            .with_format(format)
+            .with_address_mode(wgpu::AddressMode::Repeat)
+            .with_min_filter(wgpu::FilterMode::Linear)
+            .with_mag_filter(wgpu::FilterMode::Linear)
+    }
+
+    /// texel used when the material has no texture in the slot
+    fn default_texel(self) -> [u8; 4] {
+        match self {
+            TextureSlot::Normal => [128, 128, 255, 255],
+            _ => [255; 4],
+        }
+    }
+}
+
+/// parameters of a material, missing textures are replaced by neutral ones
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialDesc<'a> {
+    pub model: ShadingModel,
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    /// Blinn-Phong specular color and exponent
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub base_color_texture: Option<&'a Texture>,
+    pub normal_texture: Option<&'a Texture>,
+    pub orm_texture: Option<&'a Texture>,
+    pub emissive_texture: Option<&'a Texture>,
+    /// alpha blended without depth writes, instead of opaque
+    pub blended: bool,
+}
+
+impl<'a> Default for MaterialDesc<'a> {
+    fn default() -> Self {
+        Self {
+            model: ShadingModel::Pbr,
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            specular: [0.5; 3],
+            shininess: 32.0,
+            metallic: 0.0,
+            roughness: 0.5,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            normal_texture: None,
+            orm_texture: None,
+            emissive_texture: None,
+            blended: false,
+        }
+    }
+}
+
+impl<'a> MaterialDesc<'a> {
+    pub fn unlit(color: [f32; 4]) -> Self {
+        Self {
+            model: ShadingModel::Unlit,
+            base_color: color,
+            ..Default::default()
+        }
+    }
+
+    pub fn blinn_phong(diffuse: [f32; 4], specular: [f32; 3], shininess: f32) -> Self {
+        Self {
+            model: ShadingModel::BlinnPhong,
+            base_color: diffuse,
+            specular,
+            shininess,
+            ..Default::default()
+        }
+    }
+
+    pub fn pbr(base_color: [f32; 4], metallic: f32, roughness: f32) -> Self {
+        Self {
+            model: ShadingModel::Pbr,
+            base_color,
+            metallic,
+            roughness,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_emissive(mut self, emissive: [f32; 3]) -> Self {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_normal_scale(mut self, scale: f32) -> Self {
+        self.normal_scale = scale;
+        self
+    }
+
+    pub fn with_occlusion_strength(mut self, strength: f32) -> Self {
+        self.occlusion_strength = strength;
+        self
+    }
+
+    pub fn with_base_color_texture(mut self, texture: &'a Texture) -> Self {
+        self.base_color_texture = Some(texture);
+        self
+    }
+
+    pub fn with_normal_texture(mut self, texture: &'a Texture) -> Self {
+        self.normal_texture = Some(texture);
+        self
+    }
+
+    pub fn with_orm_texture(mut self, texture: &'a Texture) -> Self {
+        self.orm_texture = Some(texture);
+        self
+    }
+
+    pub fn with_emissive_texture(mut self, texture: &'a Texture) -> Self {
+        self.emissive_texture = Some(texture);
+        self
+    }
+
+    /// blend with the alpha of the base color, draw these materials after the opaque ones
+    pub fn with_blending(mut self, blended: bool) -> Self {
+        self.blended = blended;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    shininess: f32,
+    specular: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    model: u32,
+}
+
+#[derive(Debug)]
+pub struct Material {
+    pub model: ShadingModel,
+    pub blended: bool,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// pipeline drawing meshes of Vertex with a material, one InstanceTransform per instance
+///
+/// bind groups: 0 camera (GpuCamera), 1 lights and shadow maps (LightBuffer), 2 material
+#[derive(Debug)]
+pub struct MaterialPipeline {
+    /// opaque materials, with depth writes
+    pub pipeline: wgpu::RenderPipeline,
+    /// blended materials, depth tested without depth writes
+    pub blended_pipeline: wgpu::RenderPipeline,
+    pub material_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// one per TextureSlot
+    defaults: Vec<Texture>,
+}
+
+const SLOTS: [TextureSlot; 4] = [
+    TextureSlot::BaseColor,
+    TextureSlot::Normal,
+    TextureSlot::Orm,
+    TextureSlot::Emissive,
+];
+
+impl MaterialPipeline {
+    /// pipeline drawing to the scene target
+    pub fn new(
+        gpu: &Gpu,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &LightBuffer,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        Self::with_format(
+            gpu,
+            camera_layout,
+            lights,
+            gpu.get_scene_format(),
+            depth_format,
+        )
+    }
+
+    pub fn with_format(
+        gpu: &Gpu,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &LightBuffer,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let device = &gpu.device;
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shadow_wgsl = match lights.shadows {
+            Some(_) => SHADOW_WGSL,
+            None => NO_SHADOW_WGSL,
+        };
+        let environment_wgsl = match lights.environment {
+            Some(_) => IBL_WGSL,
+            None => NO_IBL_WGSL,
+        };
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("material_shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{LIGHTING_WGSL}{shadow_wgsl}{environment_wgsl}{MATERIAL_WGSL}").into(),
+            ),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("material_pipeline_layout"),
+            bind_group_layouts: &[camera_layout, &lights.bind_group_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let instance_layout = InstanceLayout::after::<Vertex, InstanceTransform>();
+        let create_pipeline = |label, blend, depth_write_enabled| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Vertex::desc(), instance_layout.desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline("material_pipeline", wgpu::BlendState::REPLACE, true);
+        let blended_pipeline = create_pipeline(
+            "material_blended_pipeline",
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("material_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let defaults = SLOTS
+            .iter()
+            .map(|slot| {
+                slot.builder()
+                    .with_data(&slot.default_texel())
+                    .build((1, 1), device, &gpu.queue)
+            })
+            .collect();
+
+        Self {
+            pipeline,
+            blended_pipeline,
+            material_layout,
+            sampler,
+            defaults,
+        }
+    }
+
+    pub fn create_material(&self, device: &wgpu::Device, desc: &MaterialDesc) -> Material {
+        let uniform = MaterialUniform {
+            base_color: desc.base_color,
+            emissive: desc.emissive,
+            shininess: desc.shininess,
+            specular: desc.specular,
+            metallic: desc.metallic,
+            roughness: desc.roughness,
+            normal_scale: desc.normal_scale,
+            occlusion_strength: desc.occlusion_strength,
+            model: match desc.model {
+                ShadingModel::Unlit => 0,
+                ShadingModel::BlinnPhong => 1,
+                ShadingModel::Pbr => 2,
+            },
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("material_buffer"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let textures = [
+            desc.base_color_texture,
+            desc.normal_texture,
+            desc.orm_texture,
+            desc.emissive_texture,
+        ];
+        let views: Vec<&wgpu::TextureView> = textures
+            .iter()
+            .zip(&self.defaults)
+            .map(|(texture, default)| &texture.unwrap_or(default).view)
+            .collect();
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("material_bind_group"),
+            layout: &self.material_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(views[2]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(views[3]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        Material {
+            model: desc.model,
+            blended: desc.blended,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// draw the instances of a mesh with a material, the blended ones go after the opaque ones
+    pub fn draw<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        camera: &'a wgpu::BindGroup,
+        lights: &'a LightBuffer,
+        material: &'a Material,
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer<InstanceTransform>,
+    ) {
+        rpass.set_pipeline(match material.blended {
+            true => &self.blended_pipeline,
+            false => &self.pipeline,
+        });
+        rpass.set_bind_group(0, camera, &[]);
+        rpass.set_bind_group(1, lights.bind_group(), &[]);
+        rpass.set_bind_group(2, &material.bind_group, &[]);
+        mesh.draw_instanced(rpass, instances);
+    }
+}
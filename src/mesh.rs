@@ -0,0 +1,456 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::{Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::graphics::{vertex, Vertex};
+use crate::instance::InstanceBuffer;
+
+/// axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// smallest box containing all the points (zero sized box if there are none)
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(p) => p,
+            None => return Self::new(Vec3::ZERO, Vec3::ZERO),
+        };
+        points.fold(Self::new(first, first), |aabb, p| Self {
+            min: aabb.min.min(p),
+            max: aabb.max.max(p),
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// indices of a mesh
+#[derive(Debug, Clone)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(i) => i.len(),
+            Self::U32(i) => i.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> wgpu::IndexFormat {
+        match self {
+            Self::U16(_) => wgpu::IndexFormat::Uint16,
+            Self::U32(_) => wgpu::IndexFormat::Uint32,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::U16(i) => bytemuck::cast_slice(i),
+            Self::U32(i) => bytemuck::cast_slice(i),
+        }
+    }
+
+    /// u16 indices if they all fit, u32 otherwise
+    pub fn compact(indices: Vec<u32>) -> Self {
+        if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            Self::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Self::U32(indices)
+        }
+    }
+}
+
+/// mesh geometry on the cpu side
+#[derive(Debug, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub aabb: Aabb,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let aabb = Aabb::from_points(vertices.iter().map(|v| Vec3::from_slice(&v.pos[..3])));
+        Self {
+            vertices,
+            indices,
+            aabb,
+        }
+    }
+
+    /// square on the XZ plane facing +Y
+    pub fn plane(size: f32) -> Self {
+        Self::grid(size, size, 1, 1)
+    }
+
+    /// subdivided rectangle on the XZ plane facing +Y
+    pub fn grid(width: f32, depth: f32, cols: u32, rows: u32) -> Self {
+        parametric(cols.max(1), rows.max(1), |uv| {
+            let pos = Vec3::new((uv.x - 0.5) * width, 0.0, (uv.y - 0.5) * depth);
+            (pos, Vec3::Y)
+        })
+    }
+
+    /// cube centered on the origin, each face has its own vertices
+    pub fn cube(size: f32) -> Self {
+        let h = size * 0.5;
+        // (normal, u, v) with u x v = normal
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ];
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (n, u, v) in faces {
+            let base = vertices.len() as u32;
+            for (cu, cv) in corners {
+                let pos = (n + u * cu + v * cv) * h;
+                let uv = [(cu + 1.0) * 0.5, (1.0 - cv) * 0.5];
+                vertices.push(vertex(pos.extend(1.0), n, uv));
+            }
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        Self::new(vertices, indices)
+    }
+
+    /// sphere made of sectors (longitude) and stacks (latitude)
+    pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Self {
+        parametric(sectors.max(3), stacks.max(2), |uv| {
+            let n = sphere_normal(uv);
+            (n * radius, n)
+        })
+    }
+
+    /// sphere made by subdividing an icosahedron
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5f32.sqrt()) * 0.5;
+        let mut positions: Vec<Vec3> = [
+            (-1.0, t, 0.0),
+            (1.0, t, 0.0),
+            (-1.0, -t, 0.0),
+            (1.0, -t, 0.0),
+            (0.0, -1.0, t),
+            (0.0, 1.0, t),
+            (0.0, -1.0, -t),
+            (0.0, 1.0, -t),
+            (t, 0.0, -1.0),
+            (t, 0.0, 1.0),
+            (-t, 0.0, -1.0),
+            (-t, 0.0, 1.0),
+        ]
+        .into_iter()
+        .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints = std::collections::HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let p = (positions[a as usize] + positions[b as usize]).normalize();
+                    positions.push(p);
+                    positions.len() as u32 - 1
+                })
+            };
+            triangles = triangles
+                .into_iter()
+                .flat_map(|[a, b, c]| {
+                    let ab = midpoint(a, b);
+                    let bc = midpoint(b, c);
+                    let ca = midpoint(c, a);
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let mut uvs: Vec<Vec2> = positions.iter().map(|&n| sphere_uv(n)).collect();
+
+        // duplicate the vertices of the triangles crossing the u seam
+        let mut seam = std::collections::HashMap::new();
+        for tri in triangles.iter_mut() {
+            let us = tri.map(|i| uvs[i as usize].x);
+            let max = us.iter().copied().fold(f32::MIN, f32::max);
+            let min = us.iter().copied().fold(f32::MAX, f32::min);
+            if max - min < 0.5 {
+                continue;
+            }
+            for i in tri.iter_mut() {
+                if uvs[*i as usize].x < 0.5 {
+                    *i = *seam.entry(*i).or_insert_with(|| {
+                        positions.push(positions[*i as usize]);
+                        uvs.push(uvs[*i as usize] + Vec2::X);
+                        positions.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        let vertices = positions
+            .iter()
+            .zip(&uvs)
+            .map(|(&n, &uv)| vertex((n * radius).extend(1.0), n, uv))
+            .collect();
+        Self::new(vertices, triangles.into_iter().flatten().collect())
+    }
+
+    /// capped cylinder along the Y axis centered on the origin
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let mut mesh = parametric(segments, 1, |uv| {
+            let n = ring_normal(uv.x);
+            (n * radius + Vec3::Y * (0.5 - uv.y) * height, n)
+        });
+        mesh.append(&disk(radius, height * 0.5, segments, Vec3::Y));
+        mesh.append(&disk(radius, -height * 0.5, segments, Vec3::NEG_Y));
+        mesh
+    }
+
+    /// capped cone along the Y axis centered on the origin, apex on +Y
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+        let slope = radius / height;
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for i in 0..segments {
+            let (u0, u1) = (i as f32 / segments as f32, (i + 1) as f32 / segments as f32);
+            let um = (u0 + u1) * 0.5;
+            let normal = |u: f32| (ring_normal(u) + Vec3::Y * slope).normalize();
+            let base = vertices.len() as u32;
+            vertices.push(vertex(
+                (Vec3::Y * height * 0.5).extend(1.0),
+                normal(um),
+                [um, 0.0],
+            ));
+            for u in [u0, u1] {
+                vertices.push(vertex(
+                    (ring_normal(u) * radius - Vec3::Y * height * 0.5).extend(1.0),
+                    normal(u),
+                    [u, 1.0],
+                ));
+            }
+            indices.extend([base, base + 1, base + 2]);
+        }
+        let mut mesh = Self::new(vertices, indices);
+        mesh.append(&disk(radius, -height * 0.5, segments, Vec3::NEG_Y));
+        mesh
+    }
+
+    /// torus around the Y axis
+    pub fn torus(
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: u32,
+        minor_segments: u32,
+    ) -> Self {
+        parametric(major_segments.max(3), minor_segments.max(3), |uv| {
+            let ring = ring_normal(uv.x);
+            let angle = uv.y * TAU;
+            let n = ring * angle.cos() - Vec3::Y * angle.sin();
+            (ring * major_radius + n * minor_radius, n)
+        })
+    }
+
+    /// merge the geometry of another mesh in this one
+    pub fn append(&mut self, other: &MeshData) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|i| i + base));
+        self.aabb = if base == 0 {
+            other.aabb
+        } else {
+            self.aabb.union(&other.aabb)
+        };
+    }
+}
+
+/// normal of a unit sphere, u goes around Y and v from the top to the bottom
+fn sphere_normal(uv: Vec2) -> Vec3 {
+    let phi = uv.y * PI;
+    ring_normal(uv.x) * phi.sin() + Vec3::Y * phi.cos()
+}
+
+/// inverse of sphere_normal
+fn sphere_uv(n: Vec3) -> Vec2 {
+    let u = (n.x.atan2(n.z) / TAU).rem_euclid(1.0);
+    let v = n.y.clamp(-1.0, 1.0).acos() / PI;
+    Vec2::new(u, v)
+}
+
+/// unit vector on the XZ plane, u in [0, 1] makes a full turn counter clockwise
+/// when seen from +Y
+fn ring_normal(u: f32) -> Vec3 {
+    let theta = u * TAU;
+    Vec3::new(theta.sin(), 0.0, theta.cos())
+}
+
+/// disk on the XZ plane at height y
+fn disk(radius: f32, y: f32, segments: u32, normal: Vec3) -> MeshData {
+    let mut vertices = vec![vertex([0.0, y, 0.0, 1.0], normal, [0.5, 0.5])];
+    for i in 0..=segments {
+        let n = ring_normal(i as f32 / segments as f32);
+        vertices.push(vertex(
+            (n * radius + Vec3::Y * y).extend(1.0),
+            normal,
+            [0.5 + n.x * 0.5, 0.5 - n.z * 0.5],
+        ));
+    }
+    let mut indices = Vec::with_capacity(segments as usize * 3);
+    for i in 1..=segments {
+        if normal.y > 0.0 {
+            indices.extend([0, i, i + 1]);
+        } else {
+            indices.extend([0, i + 1, i]);
+        }
+    }
+    MeshData::new(vertices, indices)
+}
+
+/// (cols + 1) x (rows + 1) grid of vertices built from a function returning
+/// the position and normal for uv in [0, 1]²
+fn parametric(cols: u32, rows: u32, f: impl Fn(Vec2) -> (Vec3, Vec3)) -> MeshData {
+    let mut vertices = Vec::with_capacity(((cols + 1) * (rows + 1)) as usize);
+    for j in 0..=rows {
+        for i in 0..=cols {
+            let uv = Vec2::new(i as f32 / cols as f32, j as f32 / rows as f32);
+            let (pos, normal) = f(uv);
+            vertices.push(vertex(pos.extend(1.0), normal, uv));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((cols * rows * 6) as usize);
+    for j in 0..rows {
+        for i in 0..cols {
+            let a = j * (cols + 1) + i;
+            let b = a + cols + 1;
+            indices.extend([a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+    MeshData::new(vertices, indices)
+}
+
+/// mesh stored in gpu buffers
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
+    pub index_count: u32,
+    pub vertex_count: u32,
+    /// bounds of the mesh, if known
+    pub aabb: Option<Aabb>,
+}
+
+impl Mesh {
+    /// upload any kind of vertices
+    pub fn new<V: bytemuck::Pod>(device: &wgpu::Device, vertices: &[V], indices: &Indices) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_vertex_buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_index_buffer"),
+            contents: indices.as_bytes(),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_format: indices.format(),
+            index_count: indices.len() as u32,
+            vertex_count: vertices.len() as u32,
+            aabb: None,
+        }
+    }
+
+    /// upload a generated or loaded mesh, using u16 indices when possible
+    pub fn from_data(device: &wgpu::Device, data: &MeshData) -> Self {
+        let mut mesh = Self::new(
+            device,
+            &data.vertices,
+            &Indices::compact(data.indices.clone()),
+        );
+        mesh.aabb = Some(data.aabb);
+        mesh
+    }
+
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        rpass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+
+    /// draw every instance, the instance buffer is bound to the vertex slot 1
+    pub fn draw_instanced<'a, T: bytemuck::Pod>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        instances: &'a InstanceBuffer<T>,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, instances.slice());
+        rpass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        rpass.draw_indexed(0..self.index_count, 0, 0..instances.len());
+    }
+}
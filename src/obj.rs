@@ -0,0 +1,543 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use glam::{Vec2, Vec3};
+use log::warn;
+
+use crate::graphics::{vertex, Vertex};
+use crate::mesh::MeshData;
+
+/// errors that can happen while loading an obj file
+#[derive(Debug)]
+pub enum ObjError {
+    /// the file could not be read
+    Io(PathBuf, std::io::Error),
+    /// a line could not be parsed
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            Self::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, e) => Some(e),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+/// material read from a .mtl file
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// Ka
+    pub ambient: [f32; 3],
+    /// Kd
+    pub diffuse: [f32; 3],
+    /// Ks
+    pub specular: [f32; 3],
+    /// Ke
+    pub emissive: [f32; 3],
+    /// Ns
+    pub shininess: f32,
+    /// d (or 1 - Tr)
+    pub dissolve: f32,
+    /// Ni
+    pub optical_density: f32,
+    pub illumination_model: Option<u32>,
+    /// texture paths, relative to the directory of the .mtl file
+    pub ambient_texture: Option<PathBuf>,
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub dissolve_texture: Option<PathBuf>,
+    pub emissive_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            ambient: [0.0; 3],
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            emissive: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            optical_density: 1.0,
+            illumination_model: None,
+            ambient_texture: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            dissolve_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+/// part of an obj file sharing a group and a material
+#[derive(Debug, Clone)]
+pub struct ObjMesh {
+    /// name of the object or group
+    pub name: String,
+    pub data: MeshData,
+    /// index in ObjModel::materials
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+/// load an obj file and the mtl libraries it references
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse_obj(&source, path.parent())
+}
+
+/// load a mtl file
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<Vec<ObjMaterial>, ObjError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse_mtl(&source, path.parent())
+}
+
+/// parse the content of an obj file
+/// mtl libraries are searched in base_dir, they are skipped if it is None
+/// polygons are triangulated as fans, so they should be convex
+/// the v texture coordinate is flipped to match wgpu (origin at the top left)
+pub fn parse_obj(source: &str, base_dir: Option<&Path>) -> Result<ObjModel, ObjError> {
+    let mut positions: Vec<Vec3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut materials: Vec<ObjMaterial> = vec![];
+
+    let mut meshes = vec![];
+    let mut builder = MeshBuilder::new("default", None);
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let rest: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&rest, line_number)?),
+            "vn" => normals.push(parse_vec3(&rest, line_number)?.normalize_or_zero()),
+            "vt" => {
+                let u = parse_f32(rest.first(), line_number)?;
+                let v = match rest.get(1) {
+                    Some(_) => parse_f32(rest.get(1), line_number)?,
+                    None => 0.0,
+                };
+                uvs.push(Vec2::new(u, 1.0 - v));
+            }
+            "f" => {
+                if rest.len() < 3 {
+                    return Err(parse_error(line_number, "face with less than 3 vertices"));
+                }
+                let corners = rest
+                    .iter()
+                    .map(|c| {
+                        parse_corner(c, positions.len(), uvs.len(), normals.len(), line_number)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let indices: Vec<u32> = corners
+                    .iter()
+                    .map(|&c| builder.vertex(c, &positions, &uvs, &normals))
+                    .collect();
+                for k in 1..indices.len() - 1 {
+                    builder
+                        .indices
+                        .extend([indices[0], indices[k], indices[k + 1]]);
+                }
+            }
+            "o" | "g" => {
+                let name = rest.join(" ");
+                let material = builder.material;
+                meshes.extend(builder.finish());
+                builder = MeshBuilder::new(&name, material);
+            }
+            "usemtl" => {
+                let name = rest.join(" ");
+                let material = materials.iter().position(|m| m.name == name);
+                if material.is_none() {
+                    warn!(target: "obj", "line {line_number}: unknown material {name}");
+                }
+                if material != builder.material {
+                    let group = builder.name.clone();
+                    meshes.extend(builder.finish());
+                    builder = MeshBuilder::new(&group, material);
+                }
+            }
+            "mtllib" => {
+                let Some(dir) = base_dir else {
+                    continue;
+                };
+                // a single file name with spaces, or several file names
+                let joined = rest.join(" ");
+                let files = match dir.join(&joined).is_file() {
+                    true => vec![joined.as_str()],
+                    false => rest.clone(),
+                };
+                for file in files {
+                    match load_mtl(dir.join(file)) {
+                        Ok(m) => materials.extend(m),
+                        Err(e) => warn!(target: "obj", "{e}"),
+                    }
+                }
+            }
+            "s" | "l" | "p" | "vp" => (),
+            _ => warn!(target: "obj", "line {line_number}: unsupported keyword {keyword}"),
+        }
+    }
+    meshes.extend(builder.finish());
+
+    Ok(ObjModel { meshes, materials })
+}
+
+/// parse the content of a mtl file, texture paths are joined to base_dir
+pub fn parse_mtl(source: &str, base_dir: Option<&Path>) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials: Vec<ObjMaterial> = vec![];
+    let texture_path = |rest: &[&str]| {
+        let file = texture_file(rest);
+        match base_dir {
+            Some(dir) => dir.join(file),
+            None => PathBuf::from(file),
+        }
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let rest: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial::new(&rest.join(" ")));
+            continue;
+        }
+        let material = materials
+            .last_mut()
+            .ok_or_else(|| parse_error(line_number, "material property before newmtl"))?;
+
+        match keyword {
+            "Ka" => material.ambient = parse_vec3(&rest, line_number)?.to_array(),
+            "Kd" => material.diffuse = parse_vec3(&rest, line_number)?.to_array(),
+            "Ks" => material.specular = parse_vec3(&rest, line_number)?.to_array(),
+            "Ke" => material.emissive = parse_vec3(&rest, line_number)?.to_array(),
+            "Ns" => material.shininess = parse_f32(rest.first(), line_number)?,
+            "d" => material.dissolve = parse_f32(rest.first(), line_number)?,
+            "Tr" => material.dissolve = 1.0 - parse_f32(rest.first(), line_number)?,
+            "Ni" => material.optical_density = parse_f32(rest.first(), line_number)?,
+            "illum" => {
+                material.illumination_model = Some(
+                    rest.first()
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(|| parse_error(line_number, "invalid illumination model"))?,
+                )
+            }
+            "map_Ka" => material.ambient_texture = Some(texture_path(&rest)),
+            "map_Kd" => material.diffuse_texture = Some(texture_path(&rest)),
+            "map_Ks" => material.specular_texture = Some(texture_path(&rest)),
+            "map_Ke" => material.emissive_texture = Some(texture_path(&rest)),
+            "map_d" => material.dissolve_texture = Some(texture_path(&rest)),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_texture = Some(texture_path(&rest))
+            }
+            _ => warn!(target: "obj", "line {line_number}: unsupported mtl keyword {keyword}"),
+        }
+    }
+
+    Ok(materials)
+}
+
+/// file name of a texture statement, after the options such as "-bm 1.0" or "-s 1 1 1"
+fn texture_file(rest: &[&str]) -> String {
+    let mut tokens = rest.iter().peekable();
+    while let Some(option) = tokens.next_if(|t| t.starts_with('-')) {
+        match *option {
+            // 1 to 3 numbers
+            "-o" | "-s" | "-t" => {
+                for _ in 0..3 {
+                    if tokens.next_if(|t| t.parse::<f32>().is_ok()).is_none() {
+                        break;
+                    }
+                }
+            }
+            "-mm" => {
+                tokens.nth(1);
+            }
+            _ => {
+                tokens.next();
+            }
+        }
+    }
+    tokens.copied().collect::<Vec<_>>().join(" ")
+}
+
+/// indices of a face corner (position, uv, normal), already resolved to 0 based
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// deduplicates the face corners of a mesh
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    vertices: Vec<Vertex>,
+    /// true for the vertices whose normal has to be generated
+    missing_normals: Vec<bool>,
+    /// obj position index of the vertices
+    position_indices: Vec<usize>,
+    indices: Vec<u32>,
+    lookup: HashMap<Corner, u32>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<usize>) -> Self {
+        Self {
+            name: String::from(name),
+            material,
+            vertices: vec![],
+            missing_normals: vec![],
+            position_indices: vec![],
+            indices: vec![],
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: Corner,
+        positions: &[Vec3],
+        uvs: &[Vec2],
+        normals: &[Vec3],
+    ) -> u32 {
+        *self.lookup.entry(corner).or_insert_with(|| {
+            let (p, t, n) = corner;
+            self.vertices.push(vertex(
+                positions[p].extend(1.0),
+                n.map(|n| normals[n]).unwrap_or(Vec3::ZERO),
+                t.map(|t| uvs[t]).unwrap_or(Vec2::ZERO),
+            ));
+            self.missing_normals.push(n.is_none());
+            self.position_indices.push(p);
+            self.vertices.len() as u32 - 1
+        })
+    }
+
+    fn finish(mut self) -> Option<ObjMesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        if self.missing_normals.iter().any(|&m| m) {
+            // area weighted face normals, accumulated per position so the uv seams
+            // stay smooth
+            let mut generated: HashMap<usize, Vec3> = HashMap::new();
+            for tri in self.indices.chunks_exact(3) {
+                let p: Vec<Vec3> = tri
+                    .iter()
+                    .map(|&i| Vec3::from_slice(&self.vertices[i as usize].pos[..3]))
+                    .collect();
+                let n = (p[1] - p[0]).cross(p[2] - p[0]);
+                for &i in tri {
+                    *generated
+                        .entry(self.position_indices[i as usize])
+                        .or_default() += n;
+                }
+            }
+            for (i, v) in self.vertices.iter_mut().enumerate() {
+                if self.missing_normals[i] {
+                    let n = generated[&self.position_indices[i]];
+                    v.normal = n.normalize_or_zero().to_array();
+                }
+            }
+        }
+
+        Some(ObjMesh {
+            name: self.name,
+            data: MeshData::new(self.vertices, self.indices),
+            material: self.material,
+        })
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> ObjError {
+    ObjError::Parse {
+        line,
+        message: String::from(message),
+    }
+}
+
+fn parse_f32(token: Option<&&str>, line: usize) -> Result<f32, ObjError> {
+    token
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| parse_error(line, "invalid number"))
+}
+
+fn parse_vec3(tokens: &[&str], line: usize) -> Result<Vec3, ObjError> {
+    Ok(Vec3::new(
+        parse_f32(tokens.first(), line)?,
+        parse_f32(tokens.get(1), line)?,
+        parse_f32(tokens.get(2), line)?,
+    ))
+}
+
+/// resolve an obj index (1 based, negative values are relative to the end)
+fn resolve_index(token: &str, count: usize, line: usize) -> Result<usize, ObjError> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| parse_error(line, "invalid index"))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(line, "index out of range"));
+    }
+    Ok(resolved as usize)
+}
+
+/// parse v, v/vt, v//vn or v/vt/vn
+fn parse_corner(
+    token: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+    line: usize,
+) -> Result<Corner, ObjError> {
+    let mut parts = token.split('/');
+    let p = resolve_index(parts.next().unwrap_or_default(), positions, line)?;
+    let t = match parts.next() {
+        Some(t) if !t.is_empty() => Some(resolve_index(t, uvs, line)?),
+        _ => None,
+    };
+    let n = match parts.next() {
+        Some(n) if !n.is_empty() => Some(resolve_index(n, normals, line)?),
+        _ => None,
+    };
+    Ok((p, t, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangulates_faces() {
+        let model = parse_obj(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nf 1/1 2/2 3/3 4/4\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let data = &model.meshes[0].data;
+        assert_eq!(data.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(data.vertices.len(), 4);
+        // the v coordinate is flipped
+        assert_eq!(data.vertices[3].uv, [0.0, 0.0]);
+        assert_eq!(data.vertices[0].uv, [0.0, 1.0]);
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let model = parse_obj(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\n",
+            None,
+        )
+        .unwrap();
+        let data = &model.meshes[0].data;
+        assert_eq!(data.vertices[1].pos, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(data.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert!(parse_obj("v 0 0 0\nf -2 1 1\n", None).is_err());
+    }
+
+    #[test]
+    fn generated_normals_are_smooth_across_uv_seams() {
+        // two faces sharing the edge 2-3 with different uvs on each side
+        let model = parse_obj(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 1 0 -1\nvt 0 0\nvt 1 0\nf 1/1 2/1 3/1\nf 2/2 4/2 3/2\n",
+            None,
+        )
+        .unwrap();
+        let data = &model.meshes[0].data;
+        let normals_of = |p: [f32; 4]| {
+            data.vertices
+                .iter()
+                .filter(|v| v.pos == p)
+                .map(|v| v.normal)
+                .collect::<Vec<_>>()
+        };
+        let seam = normals_of([1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(seam.len(), 2);
+        assert_eq!(seam[0], seam[1]);
+        let n = Vec3::from_array(seam[0]);
+        assert!((n - Vec3::new(1.0, 0.0, 1.0).normalize()).length() < 1e-5);
+    }
+
+    #[test]
+    fn materials_with_spaces() {
+        let dir = std::env::temp_dir().join(format!("obj-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("my materials.mtl"),
+            "newmtl red paint\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n",
+        )
+        .unwrap();
+
+        let model = parse_obj(
+            "mtllib my materials.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red paint\nf 1 2 3\nusemtl blue\nf 1 3 2\n",
+            Some(&dir),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.materials[0].name, "red paint");
+        assert_eq!(model.materials[0].diffuse, [1.0, 0.0, 0.0]);
+        let materials: Vec<_> = model.meshes.iter().map(|m| m.material).collect();
+        assert_eq!(materials, vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn texture_paths_with_spaces() {
+        let materials = parse_mtl(
+            "newmtl wood\nmap_Kd old wood.png\nmap_Bump -bm 0.5 -s 2 2 -clamp on wood normal.png\n",
+            Some(Path::new("textures")),
+        )
+        .unwrap();
+        let material = &materials[0];
+        assert_eq!(
+            material.diffuse_texture,
+            Some(PathBuf::from("textures/old wood.png"))
+        );
+        assert_eq!(
+            material.normal_texture,
+            Some(PathBuf::from("textures/wood normal.png"))
+        );
+    }
+}
@@ -0,0 +1,1154 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::gpu::Gpu;
+use crate::graphics::{Texture, TextureBuilder};
+use crate::layout::GpuUniform;
+use crate::render_target::{RenderTarget, RenderTargetBuilder, RenderTargetTextures};
+
+/// declarations shared by the effects, an effect defines `fn effect(uv: vec2<f32>) -> vec4<f32>`
+///
+/// `input_texture` is the output of the previous effect, `param(i)` reads the i-th parameter
+pub const POST_WGSL: &str = r#"
+struct Params {
+    values: array<vec4<f32>, 4>,
+}
+
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+fn param(i: u32) -> f32 {
+    return params.values[i / 4u][i % 4u];
+}
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0);
+}
+
+fn input_texel() -> vec2<f32> {
+    return 1.0 / vec2<f32>(textureDimensions(input_texture));
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(0.299, 0.587, 0.114));
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return effect(in.uv);
+}
+"#;
+
+const COPY_WGSL: &str = r#"
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    return sample_input(uv);
+}
+"#;
+
+const VIGNETTE_WGSL: &str = r#"
+// intensity, radius, smoothness
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let color = sample_input(uv);
+    let d = distance(uv, vec2<f32>(0.5)) * 1.41421356;
+    let shade = 1.0 - param(0u) * smoothstep(param(1u), param(1u) + param(2u), d);
+    return vec4<f32>(color.rgb * shade, color.a);
+}
+"#;
+
+const CHROMATIC_ABERRATION_WGSL: &str = r#"
+// strength, the red and blue channels are shifted away from the center
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let offset = (uv - 0.5) * param(0u);
+    let r = sample_input(uv + offset).r;
+    let center = sample_input(uv);
+    let b = sample_input(uv - offset).b;
+    return vec4<f32>(r, center.g, b, center.a);
+}
+"#;
+
+const FXAA_WGSL: &str = r#"
+// span max, reduce mul, reduce min
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let texel = input_texel();
+    let center = sample_input(uv);
+    let nw = luma(sample_input(uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let ne = luma(sample_input(uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let sw = luma(sample_input(uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let se = luma(sample_input(uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let m = luma(center.rgb);
+    let luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    let luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    // blur along the edge
+    var dir = vec2<f32>(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    let reduce = max((nw + ne + sw + se) * 0.25 * param(1u), param(2u));
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2<f32>(-param(0u)), vec2<f32>(param(0u))) * texel;
+
+    let a = 0.5 * (sample_input(uv + dir * (1.0 / 3.0 - 0.5)).rgb
+        + sample_input(uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    let b = a * 0.5 + 0.25 * (sample_input(uv - dir * 0.5).rgb + sample_input(uv + dir * 0.5).rgb);
+    let luma_b = luma(b);
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4<f32>(a, center.a);
+    }
+    return vec4<f32>(b, center.a);
+}
+"#;
+
+const LUT_WGSL: &str = r#"
+@group(0) @binding(3) var lut_texture: texture_3d<f32>;
+
+// intensity, domain min, domain max
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let color = sample_input(uv);
+    let size = f32(textureDimensions(lut_texture).x);
+    let domain = clamp((color.rgb - param(1u)) / (param(2u) - param(1u)), vec3<f32>(0.0), vec3<f32>(1.0));
+    // centers of the first and last texels
+    let coords = domain * (size - 1.0) / size + 0.5 / size;
+    let graded = textureSampleLevel(lut_texture, input_sampler, coords, 0.0).rgb;
+    return vec4<f32>(mix(color.rgb, graded, param(0u)), color.a);
+}
+"#;
+
+const BLOOM_COMMON_WGSL: &str = r#"
+// threshold, knee, intensity, radius
+
+// 13 taps downsample, weighted to avoid the flickering of small bright spots
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let t = input_texel();
+    let a = sample_input(uv + t * vec2<f32>(-2.0, -2.0)).rgb;
+    let b = sample_input(uv + t * vec2<f32>(0.0, -2.0)).rgb;
+    let c = sample_input(uv + t * vec2<f32>(2.0, -2.0)).rgb;
+    let d = sample_input(uv + t * vec2<f32>(-1.0, -1.0)).rgb;
+    let e = sample_input(uv + t * vec2<f32>(1.0, -1.0)).rgb;
+    let f = sample_input(uv + t * vec2<f32>(-2.0, 0.0)).rgb;
+    let g = sample_input(uv).rgb;
+    let h = sample_input(uv + t * vec2<f32>(2.0, 0.0)).rgb;
+    let i = sample_input(uv + t * vec2<f32>(-1.0, 1.0)).rgb;
+    let j = sample_input(uv + t * vec2<f32>(1.0, 1.0)).rgb;
+    let k = sample_input(uv + t * vec2<f32>(-2.0, 2.0)).rgb;
+    let l = sample_input(uv + t * vec2<f32>(0.0, 2.0)).rgb;
+    let m = sample_input(uv + t * vec2<f32>(2.0, 2.0)).rgb;
+    return (d + e + i + j) * 0.125
+        + (a + b + f + g) * 0.03125
+        + (b + c + g + h) * 0.03125
+        + (f + g + k + l) * 0.03125
+        + (g + h + l + m) * 0.03125;
+}
+
+// keep what is above the threshold, with a soft knee
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = param(1u);
+    var soft = clamp(brightness - param(0u) + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+    let contribution = max(soft, brightness - param(0u)) / max(brightness, 0.0001);
+    return color * contribution;
+}
+"#;
+
+const BLOOM_DOWNSAMPLE_WGSL: &str = r#"
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    return vec4<f32>(downsample(uv), 1.0);
+}
+"#;
+
+const BLOOM_THRESHOLD_WGSL: &str = r#"
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    return vec4<f32>(threshold(downsample(uv)), 1.0);
+}
+"#;
+
+const BLOOM_UPSAMPLE_WGSL: &str = r#"
+// 3x3 tent filter, added to the larger mip
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let t = input_texel() * param(3u);
+    var color = sample_input(uv).rgb * 4.0;
+    color += (sample_input(uv + vec2<f32>(-t.x, 0.0)).rgb
+        + sample_input(uv + vec2<f32>(t.x, 0.0)).rgb
+        + sample_input(uv + vec2<f32>(0.0, -t.y)).rgb
+        + sample_input(uv + vec2<f32>(0.0, t.y)).rgb) * 2.0;
+    color += sample_input(uv - t).rgb
+        + sample_input(uv + t).rgb
+        + sample_input(uv + vec2<f32>(-t.x, t.y)).rgb
+        + sample_input(uv + vec2<f32>(t.x, -t.y)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+"#;
+
+const BLOOM_COMPOSITE_WGSL: &str = r#"
+@group(0) @binding(3) var bloom_texture: texture_2d<f32>;
+
+fn effect(uv: vec2<f32>) -> vec4<f32> {
+    let color = sample_input(uv);
+    let bloom = textureSampleLevel(bloom_texture, input_sampler, uv, 0.0).rgb;
+    return vec4<f32>(color.rgb + bloom * param(2u), color.a);
+}
+"#;
+
+/// a full screen pass of the chain
+pub trait Effect: fmt::Debug {
+    fn name(&self) -> &str;
+
+    fn enabled(&self) -> bool;
+
+    fn set_enabled(&mut self, enabled: bool);
+
+    /// follow the size of the chain
+    fn resize(&mut self, _gpu: &Gpu, _size: (u32, u32)) {}
+
+    /// draw `input` with the effect into `output`
+    fn render(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    );
+
+    /// parameter panel
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+}
+
+/// tunable value of a ShaderEffect, read with `param(i)` in the shader
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectParam {
+    pub name: String,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl EffectParam {
+    pub fn new(name: &str, value: f32, min: f32, max: f32) -> Self {
+        Self {
+            name: name.to_owned(),
+            value,
+            min,
+            max,
+        }
+    }
+}
+
+/// maximum number of parameters of a ShaderEffect
+pub const MAX_PARAMS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct ParamsUniform {
+    values: [[f32; 4]; 4],
+}
+
+impl ParamsUniform {
+    fn new(params: &[EffectParam]) -> Self {
+        let mut values = [[0.0; 4]; 4];
+        for (i, param) in params.iter().take(MAX_PARAMS).enumerate() {
+            values[i / 4][i % 4] = param.value;
+        }
+        Self { values }
+    }
+}
+
+/// pipeline of a full screen pass, with an optional extra texture at binding 3
+#[derive(Debug)]
+struct FullscreenPass {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+}
+
+impl FullscreenPass {
+    fn new(
+        gpu: &Gpu,
+        label: &str,
+        effect_wgsl: &str,
+        format: wgpu::TextureFormat,
+        extra: Option<wgpu::TextureViewDimension>,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        let device = &gpu.device;
+
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        if let Some(view_dimension) = extra {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_bind_group_layout"),
+            entries: &entries,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(format!("{POST_WGSL}{effect_wgsl}").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self { pipeline, layout }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        params: &wgpu::Buffer,
+        extra: Option<&wgpu::TextureView>,
+        output: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(input),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+        ];
+        if let Some(extra) = extra {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(extra),
+            });
+        }
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post_bind_group"),
+            layout: &self.layout,
+            entries: &entries,
+        });
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("post_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+fn linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("post_sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn params_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("post_params_buffer"),
+        size: std::mem::size_of::<ParamsUniform>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// single pass effect from a WGSL `effect` function, see POST_WGSL
+#[derive(Debug)]
+pub struct ShaderEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub params: Vec<EffectParam>,
+    pass: FullscreenPass,
+    sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+    /// texture bound at binding 3
+    extra: Option<Texture>,
+}
+
+impl ShaderEffect {
+    /// effect from user WGSL, at most MAX_PARAMS parameters
+    pub fn custom(
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        name: &str,
+        wgsl: &str,
+        params: Vec<EffectParam>,
+    ) -> Self {
+        Self::build(gpu, format, name, wgsl, params, None)
+    }
+
+    fn build(
+        gpu: &Gpu,
+        format: wgpu::TextureFormat,
+        name: &str,
+        wgsl: &str,
+        params: Vec<EffectParam>,
+        extra: Option<(Texture, wgpu::TextureViewDimension)>,
+    ) -> Self {
+        assert!(params.len() <= MAX_PARAMS, "too many effect parameters");
+        let pass = FullscreenPass::new(
+            gpu,
+            name,
+            wgsl,
+            format,
+            extra.as_ref().map(|(_, dimension)| *dimension),
+            None,
+        );
+        Self {
+            name: name.to_owned(),
+            enabled: true,
+            params,
+            pass,
+            sampler: linear_sampler(&gpu.device),
+            buffer: params_buffer(&gpu.device),
+            extra: extra.map(|(texture, _)| texture),
+        }
+    }
+
+    pub fn vignette(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        Self::custom(
+            gpu,
+            format,
+            "vignette",
+            VIGNETTE_WGSL,
+            vec![
+                EffectParam::new("intensity", 0.5, 0.0, 1.0),
+                EffectParam::new("radius", 0.6, 0.0, 1.0),
+                EffectParam::new("smoothness", 0.45, 0.01, 1.0),
+            ],
+        )
+    }
+
+    pub fn chromatic_aberration(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        Self::custom(
+            gpu,
+            format,
+            "chromatic aberration",
+            CHROMATIC_ABERRATION_WGSL,
+            vec![EffectParam::new("strength", 0.01, 0.0, 0.05)],
+        )
+    }
+
+    /// fast approximate anti aliasing, best placed after the tonemapping
+    pub fn fxaa(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        Self::custom(
+            gpu,
+            format,
+            "fxaa",
+            FXAA_WGSL,
+            vec![
+                EffectParam::new("span max", 8.0, 1.0, 16.0),
+                EffectParam::new("reduce mul", 1.0 / 8.0, 0.0, 0.5),
+                EffectParam::new("reduce min", 1.0 / 128.0, 0.0, 0.1),
+            ],
+        )
+    }
+
+    /// color grading with a 3d lookup table
+    pub fn color_grading(gpu: &Gpu, format: wgpu::TextureFormat, lut: &CubeLut) -> Self {
+        Self::build(
+            gpu,
+            format,
+            "color grading",
+            LUT_WGSL,
+            vec![
+                EffectParam::new("intensity", 1.0, 0.0, 1.0),
+                EffectParam::new("domain min", lut.domain_min, -1.0, 1.0),
+                EffectParam::new("domain max", lut.domain_max, 0.0, 16.0),
+            ],
+            Some((lut.texture(gpu), wgpu::TextureViewDimension::D3)),
+        )
+    }
+
+    pub fn param(&self, name: &str) -> Option<f32> {
+        self.params.iter().find(|p| p.name == name).map(|p| p.value)
+    }
+
+    pub fn set_param(&mut self, name: &str, value: f32) {
+        if let Some(param) = self.params.iter_mut().find(|p| p.name == name) {
+            param.value = value;
+        }
+    }
+}
+
+impl Effect for ShaderEffect {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn render(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        gpu.queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&ParamsUniform::new(&self.params)),
+        );
+        self.pass.draw(
+            gpu,
+            encoder,
+            input,
+            &self.sampler,
+            &self.buffer,
+            self.extra.as_ref().map(|texture| &texture.view),
+            output,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        );
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        for param in &mut self.params {
+            ui.add(egui::Slider::new(&mut param.value, param.min..=param.max).text(&param.name));
+        }
+    }
+}
+
+/// bloom from the bright parts of the image, blurred over a chain of mips
+#[derive(Debug)]
+pub struct Bloom {
+    pub enabled: bool,
+    /// brightness from which the pixels bloom
+    pub threshold: f32,
+    /// softness of the threshold
+    pub knee: f32,
+    pub intensity: f32,
+    /// spread of the upsampling filter, in texels
+    pub radius: f32,
+    /// number of mips of the blur
+    pub mip_count: u32,
+    threshold_pass: FullscreenPass,
+    downsample_pass: FullscreenPass,
+    upsample_pass: FullscreenPass,
+    composite_pass: FullscreenPass,
+    mips: Vec<Texture>,
+    sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+}
+
+const BLOOM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+impl Bloom {
+    pub fn new(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.3,
+            radius: 1.0,
+            mip_count: 6,
+            threshold_pass: FullscreenPass::new(
+                gpu,
+                "bloom_threshold",
+                &format!("{BLOOM_COMMON_WGSL}{BLOOM_THRESHOLD_WGSL}"),
+                BLOOM_FORMAT,
+                None,
+                None,
+            ),
+            downsample_pass: FullscreenPass::new(
+                gpu,
+                "bloom_downsample",
+                &format!("{BLOOM_COMMON_WGSL}{BLOOM_DOWNSAMPLE_WGSL}"),
+                BLOOM_FORMAT,
+                None,
+                None,
+            ),
+            upsample_pass: FullscreenPass::new(
+                gpu,
+                "bloom_upsample",
+                BLOOM_UPSAMPLE_WGSL,
+                BLOOM_FORMAT,
+                None,
+                Some(additive),
+            ),
+            composite_pass: FullscreenPass::new(
+                gpu,
+                "bloom_composite",
+                BLOOM_COMPOSITE_WGSL,
+                format,
+                Some(wgpu::TextureViewDimension::D2),
+                None,
+            ),
+            mips: Vec::new(),
+            sampler: linear_sampler(&gpu.device),
+            buffer: params_buffer(&gpu.device),
+        }
+    }
+}
+
+impl Effect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn resize(&mut self, gpu: &Gpu, size: (u32, u32)) {
+        // the first mip is half the size of the chain
+        let levels = size.0.min(size.1).max(2).ilog2();
+        self.mips = (1..=self.mip_count.clamp(1, levels))
+            .map(|level| {
+                TextureBuilder::new()
+                    .with_format(BLOOM_FORMAT)
+                    .with_usages(wgpu::TextureUsages::RENDER_ATTACHMENT)
+                    .build(
+                        ((size.0 >> level).max(1), (size.1 >> level).max(1)),
+                        &gpu.device,
+                        &gpu.queue,
+                    )
+            })
+            .collect();
+    }
+
+    fn render(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let params = ParamsUniform {
+            values: [
+                [
+                    self.threshold,
+                    self.knee.max(0.0001),
+                    self.intensity,
+                    self.radius,
+                ],
+                [0.0; 4],
+                [0.0; 4],
+                [0.0; 4],
+            ],
+        };
+        gpu.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&params));
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+
+        let Some(first) = self.mips.first() else {
+            return;
+        };
+        self.threshold_pass.draw(
+            gpu,
+            encoder,
+            input,
+            &self.sampler,
+            &self.buffer,
+            None,
+            &first.view,
+            clear,
+        );
+        for pair in self.mips.windows(2) {
+            self.downsample_pass.draw(
+                gpu,
+                encoder,
+                &pair[0].view,
+                &self.sampler,
+                &self.buffer,
+                None,
+                &pair[1].view,
+                clear,
+            );
+        }
+        // each mip is blurred and added to the next larger one
+        for pair in self.mips.windows(2).rev() {
+            self.upsample_pass.draw(
+                gpu,
+                encoder,
+                &pair[1].view,
+                &self.sampler,
+                &self.buffer,
+                None,
+                &pair[0].view,
+                wgpu::LoadOp::Load,
+            );
+        }
+        self.composite_pass.draw(
+            gpu,
+            encoder,
+            input,
+            &self.sampler,
+            &self.buffer,
+            Some(&first.view),
+            output,
+            clear,
+        );
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.threshold, 0.0..=10.0).text("threshold"));
+        ui.add(egui::Slider::new(&mut self.knee, 0.0..=1.0).text("knee"));
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=2.0).text("intensity"));
+        ui.add(egui::Slider::new(&mut self.radius, 0.5..=4.0).text("radius"));
+    }
+}
+
+#[derive(Debug)]
+pub enum LutError {
+    Io(std::io::Error),
+    /// invalid line, with its number
+    Parse(usize, String),
+    /// the number of entries is not size^3
+    Size {
+        expected: usize,
+        found: usize,
+    },
+    /// a size smaller than 2 or whose cube overflows
+    InvalidSize(u32),
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LutError::Io(e) => write!(f, "failed to read the lut: {e}"),
+            LutError::Parse(line, msg) => write!(f, "line {line}: {msg}"),
+            LutError::Size { expected, found } => {
+                write!(f, "expected {expected} lut entries, found {found}")
+            }
+            LutError::InvalidSize(size) => write!(f, "invalid lut size {size}"),
+        }
+    }
+}
+
+impl std::error::Error for LutError {}
+
+impl From<std::io::Error> for LutError {
+    fn from(e: std::io::Error) -> Self {
+        LutError::Io(e)
+    }
+}
+
+/// 3d color lookup table read from a `.cube` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub size: u32,
+    /// rgb entries, red varying the fastest
+    pub data: Vec<[f32; 3]>,
+    pub domain_min: f32,
+    pub domain_max: f32,
+}
+
+impl CubeLut {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LutError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, LutError> {
+        let mut size = 0;
+        let mut data = Vec::new();
+        let mut domain_min = 0.0;
+        let mut domain_max = 1.0;
+
+        for (number, line) in source.lines().enumerate() {
+            let number = number + 1;
+            let line = line.trim();
+            let err = |msg: &str| LutError::Parse(number, msg.to_owned());
+            let mut tokens = line.split_whitespace();
+            let Some(first) = tokens.next() else {
+                continue;
+            };
+            let float = |tokens: &mut std::str::SplitWhitespace| -> Result<f32, LutError> {
+                tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(|| err("expected a number"))
+            };
+            match first {
+                _ if first.starts_with('#') => {}
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(err("1d luts are not supported")),
+                "LUT_3D_SIZE" => {
+                    size = tokens
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .filter(|&size| entry_count(size).is_some())
+                        .ok_or_else(|| err("invalid size"))?;
+                }
+                // the domain is the same for the 3 channels, the first one is kept
+                "DOMAIN_MIN" => domain_min = float(&mut tokens)?,
+                "DOMAIN_MAX" => domain_max = float(&mut tokens)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    domain_min = float(&mut tokens)?;
+                    domain_max = float(&mut tokens)?;
+                }
+                _ => {
+                    let r = first.parse().map_err(|_| err("unknown keyword"))?;
+                    data.push([r, float(&mut tokens)?, float(&mut tokens)?]);
+                }
+            }
+        }
+
+        let expected = entry_count(size).unwrap_or(0);
+        if size == 0 || data.len() != expected {
+            return Err(LutError::Size {
+                expected,
+                found: data.len(),
+            });
+        }
+        Ok(Self {
+            size,
+            data,
+            domain_min,
+            domain_max,
+        })
+    }
+
+    /// identity table of the given size, at least 2
+    pub fn identity(size: u32) -> Result<Self, LutError> {
+        let count = entry_count(size)
+            .filter(|_| size >= 2)
+            .ok_or(LutError::InvalidSize(size))?;
+        let (n, max) = (size as usize, (size - 1) as f32);
+        let data = (0..count)
+            .map(|i| {
+                let (r, g, b) = (i % n, i / n % n, i / (n * n));
+                [r as f32 / max, g as f32 / max, b as f32 / max]
+            })
+            .collect();
+        Ok(Self {
+            size,
+            data,
+            domain_min: 0.0,
+            domain_max: 1.0,
+        })
+    }
+
+    /// Rgba16Float 3d texture of the table
+    pub fn texture(&self, gpu: &Gpu) -> Texture {
+        let data: Vec<u16> = self
+            .data
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0].map(f16_bits))
+            .collect();
+        TextureBuilder::new()
+            .with_format(wgpu::TextureFormat::Rgba16Float)
+            .with_depth(self.size)
+            .with_data(bytemuck::cast_slice(&data))
+            .build((self.size, self.size), &gpu.device, &gpu.queue)
+    }
+}
+
+/// size^3 entries of a table, None when it is 0 or overflows
+fn entry_count(size: u32) -> Option<usize> {
+    (size as usize)
+        .checked_mul(size as usize)?
+        .checked_mul(size as usize)
+        .filter(|&count| count > 0)
+}
+
+/// half float bits of a value, small values are flushed to zero
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        sign
+    } else if exponent >= 31 {
+        sign | 0x7c00
+    } else {
+        sign | (exponent as u16) << 10 | (mantissa >> 13) as u16
+    }
+}
+
+/// chain of effects between two textures of the size of the scene target
+///
+/// the scene is drawn into `view()`, then `render` runs the enabled effects in order, the last
+/// one writing to the output
+#[derive(Debug)]
+pub struct PostProcess {
+    pub effects: Vec<Box<dyn Effect>>,
+    format: wgpu::TextureFormat,
+    /// two colors, the effects ping-pong between them
+    target: RenderTarget,
+    /// textures of the target used by the effects
+    textures: Arc<RenderTargetTextures>,
+    copy: ShaderEffect,
+}
+
+impl PostProcess {
+    /// chain in the scene format
+    pub fn new(gpu: &Gpu) -> Self {
+        Self::with_format(gpu, gpu.get_scene_format())
+    }
+
+    pub fn with_format(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let target = RenderTargetBuilder::new()
+            .with_color(format)
+            .with_color(format)
+            .build(gpu);
+        Self {
+            effects: Vec::new(),
+            format,
+            textures: target.textures(),
+            target,
+            copy: ShaderEffect::custom(gpu, format, "copy", COPY_WGSL, Vec::new()),
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// append an effect to the chain
+    pub fn with_effect<E: Effect + 'static>(mut self, gpu: &Gpu, effect: E) -> Self {
+        self.push(gpu, effect);
+        self
+    }
+
+    pub fn push<E: Effect + 'static>(&mut self, gpu: &Gpu, mut effect: E) {
+        effect.resize(gpu, self.size());
+        self.effects.push(Box::new(effect));
+    }
+
+    /// first effect with the name
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut Box<dyn Effect>> {
+        self.effects.iter_mut().find(|e| e.name() == name)
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.textures.size
+    }
+
+    /// follow the size of the scene target, cheap to call from AppInstance::update
+    pub fn resize(&mut self, gpu: &Gpu) {
+        self.target.resize(gpu);
+        let textures = self.target.textures();
+        if Arc::ptr_eq(&textures, &self.textures) {
+            return;
+        }
+        self.textures = textures;
+        let size = self.size();
+        for effect in &mut self.effects {
+            effect.resize(gpu, size);
+        }
+    }
+
+    /// input of the chain, the scene is drawn here
+    pub fn view(&self) -> &wgpu::TextureView {
+        self.textures.view(0)
+    }
+
+    /// run the enabled effects from view() to output
+    pub fn render(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+    ) {
+        let enabled: Vec<&dyn Effect> = self
+            .effects
+            .iter()
+            .filter(|e| e.enabled())
+            .map(|e| e.as_ref())
+            .collect();
+        if enabled.is_empty() {
+            self.copy.render(gpu, encoder, self.view(), output);
+            return;
+        }
+
+        let mut source = 0;
+        for (i, effect) in enabled.iter().enumerate() {
+            let target = match i + 1 == enabled.len() {
+                true => output,
+                false => self.textures.view(1 - source),
+            };
+            effect.render(gpu, encoder, self.textures.view(source), target);
+            source = 1 - source;
+        }
+    }
+
+    /// toggles and parameter panels of the effects
+    #[cfg(feature = "egui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        for (i, effect) in self.effects.iter_mut().enumerate() {
+            let mut enabled = effect.enabled();
+            ui.push_id(i, |ui| {
+                egui::CollapsingHeader::new(effect.name().to_owned()).show(ui, |ui| {
+                    if ui.checkbox(&mut enabled, "enabled").changed() {
+                        effect.set_enabled(enabled);
+                    }
+                    effect.ui(ui);
+                });
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cube_lut() {
+        let lut = CubeLut::parse(
+            "# comment\nTITLE \"test\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n\n\
+             0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )
+        .unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_max, 2.0);
+        assert_eq!(lut.data[1], [1.0, 0.0, 0.0]);
+        let identity = CubeLut {
+            domain_max: 2.0,
+            ..CubeLut::identity(2).unwrap()
+        };
+        assert_eq!(lut, identity);
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        let err = CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n").unwrap_err();
+        assert!(matches!(
+            err,
+            LutError::Size {
+                expected: 8,
+                found: 2
+            }
+        ));
+        assert!(matches!(
+            CubeLut::parse("LUT_3D_SIZE 0\n"),
+            Err(LutError::Parse(1, _))
+        ));
+        assert!(matches!(
+            CubeLut::parse("LUT_3D_SIZE 4294967295\n"),
+            Err(LutError::Parse(1, _))
+        ));
+        assert!(matches!(
+            CubeLut::identity(0),
+            Err(LutError::InvalidSize(0))
+        ));
+        assert!(matches!(
+            CubeLut::identity(1),
+            Err(LutError::InvalidSize(1))
+        ));
+    }
+
+    #[test]
+    fn f16_bits_of_round_values() {
+        assert_eq!(f16_bits(0.0), 0x0000);
+        assert_eq!(f16_bits(-0.0), 0x8000);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(0.5), 0x3800);
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+        assert_eq!(f16_bits(1.0e6), 0x7c00);
+        // below the smallest normal half
+        assert_eq!(f16_bits(1.0e-6), 0x0000);
+    }
+}
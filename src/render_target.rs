@@ -0,0 +1,284 @@
+use std::sync::{Arc, RwLock, Weak};
+
+use crate::gpu::Gpu;
+use crate::graphics::{Texture, TextureBuilder};
+
+/// size of a render target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderTargetSize {
+    /// the size of the scene target multiplied by the scale
+    Scaled(f32),
+    /// a size that does not follow the scene target
+    Fixed(u32, u32),
+}
+
+impl Default for RenderTargetSize {
+    fn default() -> Self {
+        RenderTargetSize::Scaled(1.0)
+    }
+}
+
+impl RenderTargetSize {
+    /// size in pixels relative to a reference size, scaled down to fit max_dimension while
+    /// keeping the aspect ratio
+    pub fn resolve(self, reference: (u32, u32), max_dimension: u32) -> (u32, u32) {
+        let (width, height) = match self {
+            RenderTargetSize::Scaled(scale) => (
+                ((reference.0 as f32 * scale).round() as u32).max(1),
+                ((reference.1 as f32 * scale).round() as u32).max(1),
+            ),
+            RenderTargetSize::Fixed(width, height) => (width.max(1), height.max(1)),
+        };
+        let largest = width.max(height);
+        if largest <= max_dimension {
+            return (width, height);
+        }
+        let fit = |size: u32| {
+            ((size as f64 * max_dimension as f64 / largest as f64).round() as u32)
+                .clamp(1, max_dimension)
+        };
+        (fit(width), fit(height))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderTargetBuilder {
+    colors: Vec<wgpu::TextureFormat>,
+    depth: Option<wgpu::TextureFormat>,
+    size: RenderTargetSize,
+    usages: wgpu::TextureUsages,
+    filter: wgpu::FilterMode,
+}
+
+impl Default for RenderTargetBuilder {
+    fn default() -> Self {
+        Self {
+            colors: Vec::new(),
+            depth: None,
+            size: RenderTargetSize::default(),
+            usages: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            filter: wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+impl RenderTargetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a color attachment
+    pub fn with_color(mut self, format: wgpu::TextureFormat) -> Self {
+        self.colors.push(format);
+        self
+    }
+
+    pub fn with_depth(mut self, format: wgpu::TextureFormat) -> Self {
+        self.depth = Some(format);
+        self
+    }
+
+    /// resolution relative to the scene target, the surface unless the app has an internal
+    /// resolution
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        assert!(
+            scale.is_finite() && scale > 0.0,
+            "invalid render target scale {scale}"
+        );
+        self.size = RenderTargetSize::Scaled(scale);
+        self
+    }
+
+    /// size that does not follow the scene target
+    pub fn with_fixed_size(mut self, width: u32, height: u32) -> Self {
+        self.size = RenderTargetSize::Fixed(width, height);
+        self
+    }
+
+    /// usages added to the textures, they are always render attachments
+    pub fn with_usages(mut self, u: wgpu::TextureUsages) -> Self {
+        self.usages |= u;
+        self
+    }
+
+    /// filter of the samplers of the textures
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// create the textures, the target follows the scene size once registered to the app
+    /// (see AppInstance::render_targets) or when RenderTarget::resize is called
+    pub fn build(self, gpu: &Gpu) -> RenderTarget {
+        let textures = self.create_textures(gpu);
+        RenderTarget(Arc::new(Shared {
+            builder: RwLock::new(self),
+            textures: RwLock::new(Arc::new(textures)),
+        }))
+    }
+
+    fn create_textures(&self, gpu: &Gpu) -> RenderTargetTextures {
+        let size = self.size.resolve(gpu.scene_size(), gpu.max_texture_size());
+        let builder = |format| {
+            TextureBuilder::new()
+                .with_format(format)
+                .with_usages(self.usages)
+                .with_min_filter(self.filter)
+                .with_mag_filter(self.filter)
+                .build(size, &gpu.device, &gpu.queue)
+        };
+        RenderTargetTextures {
+            colors: self.colors.iter().map(|&format| builder(format)).collect(),
+            depth: self.depth.map(builder),
+            size,
+        }
+    }
+}
+
+/// textures of a render target at its current size
+#[derive(Debug)]
+pub struct RenderTargetTextures {
+    pub colors: Vec<Texture>,
+    pub depth: Option<Texture>,
+    pub size: (u32, u32),
+}
+
+impl RenderTargetTextures {
+    /// view of the color attachment i
+    pub fn view(&self, i: usize) -> &wgpu::TextureView {
+        &self.colors[i].view
+    }
+
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        self.depth.as_ref().map(|texture| &texture.view)
+    }
+
+    pub fn color_attachment(
+        &self,
+        i: usize,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: self.view(i),
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// attachments of all the colors, in order
+    pub fn color_attachments(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Vec<Option<wgpu::RenderPassColorAttachment<'_>>> {
+        (0..self.colors.len())
+            .map(|i| Some(self.color_attachment(i, load)))
+            .collect()
+    }
+
+    pub fn depth_attachment(
+        &self,
+        load: wgpu::LoadOp<f32>,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth_view()
+            .map(|view| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            })
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    builder: RwLock<RenderTargetBuilder>,
+    textures: RwLock<Arc<RenderTargetTextures>>,
+}
+
+/// textures following the size of the scene target, cheap to clone
+///
+/// the textures are recreated by the resizes, keep the Arc returned by `textures()` while their
+/// views are used
+#[derive(Debug, Clone)]
+pub struct RenderTarget(Arc<Shared>);
+
+impl RenderTarget {
+    /// textures at the current size
+    pub fn textures(&self) -> Arc<RenderTargetTextures> {
+        self.0.textures.read().unwrap().clone()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.0.textures.read().unwrap().size
+    }
+
+    /// change the scale or the fixed size, the textures are recreated if needed
+    pub fn set_size(&self, gpu: &Gpu, size: RenderTargetSize) {
+        self.0.builder.write().unwrap().size = size;
+        self.resize(gpu);
+    }
+
+    /// follow the size of the scene target, cheap when it did not change
+    pub fn resize(&self, gpu: &Gpu) {
+        let builder = self.0.builder.read().unwrap();
+        let size = builder
+            .size
+            .resolve(gpu.scene_size(), gpu.max_texture_size());
+        if self.0.textures.read().unwrap().size == size {
+            return;
+        }
+        *self.0.textures.write().unwrap() = Arc::new(builder.create_textures(gpu));
+    }
+}
+
+/// render targets resized together, the dropped ones are forgotten
+///
+/// the app keeps one and resizes it after Gpu::resize_surface
+#[derive(Debug, Default)]
+pub struct RenderTargets {
+    targets: Vec<Weak<Shared>>,
+}
+
+impl RenderTargets {
+    pub fn register(&mut self, target: &RenderTarget) {
+        self.targets.push(Arc::downgrade(&target.0));
+    }
+
+    pub fn resize(&mut self, gpu: &Gpu) {
+        self.targets.retain(|target| match target.upgrade() {
+            Some(shared) => {
+                RenderTarget(shared).resize(gpu);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_fits_the_texture_limit() {
+        let scaled = RenderTargetSize::Scaled(4.0);
+        assert_eq!(scaled.resolve((1280, 720), 8192), (5120, 2880));
+        assert_eq!(scaled.resolve((2560, 1440), 8192), (8192, 4608));
+        assert_eq!(RenderTargetSize::Scaled(0.5).resolve((3, 3), 8192), (2, 2));
+        assert_eq!(
+            RenderTargetSize::Fixed(0, 20000).resolve((1, 1), 8192),
+            (1, 8192)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid render target scale")]
+    fn rejects_nan_scales() {
+        RenderTargetBuilder::new().with_scale(f32::NAN);
+    }
+}
@@ -0,0 +1,860 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::camera::CameraUniform;
+use crate::gpu::Gpu;
+use crate::graphics::Vertex;
+use crate::instance::{InstanceBuffer, InstanceLayout, InstanceTransform};
+use crate::layout::GpuUniform;
+use crate::material::Light;
+use crate::mesh::Mesh;
+
+/// WGSL library sampling the shadow maps, follows LIGHTING_WGSL
+///
+/// the shadow maps are bound to the lights group, as done by LightBuffer::with_shadows
+pub const SHADOW_WGSL: &str = r#"
+struct ShadowSettings {
+    cascade_splits: vec4<f32>,
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: u32,
+    cascade_count: u32,
+}
+
+struct ShadowLayer {
+    view_proj: mat4x4<f32>,
+    texel_size: f32,
+}
+
+struct Shadows {
+    settings: ShadowSettings,
+    layers: array<ShadowLayer>,
+}
+
+@group(1) @binding(1) var<storage, read> shadows: Shadows;
+@group(1) @binding(2) var shadow_maps: texture_depth_2d_array;
+@group(1) @binding(3) var shadow_sampler: sampler_comparison;
+
+// fraction of the texels of a layer around the point that are lit
+fn shadow_pcf(layer: u32, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let data = shadows.layers[layer];
+    // offset along the normal by a number of texels, texel_size is the size of a texel at w = 1
+    let w = (data.view_proj * vec4<f32>(position, 1.0)).w;
+    let offset = normal * shadows.settings.normal_bias * data.texel_size * w;
+    let clip = data.view_proj * vec4<f32>(position + offset, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let depth = ndc.z - shadows.settings.depth_bias;
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_maps));
+    let radius = i32(shadows.settings.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let coords = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, coords, layer, depth);
+        }
+    }
+    let taps = 2 * radius + 1;
+    return lit / f32(taps * taps);
+}
+
+// fraction of the light reaching a point, view_depth is the distance to the camera plane
+fn light_shadow(light: Light, position: vec3<f32>, normal: vec3<f32>, view_depth: f32) -> f32 {
+    if light.shadow_layer < 0 {
+        return 1.0;
+    }
+
+    var layer = u32(light.shadow_layer);
+    if light.kind == LIGHT_DIRECTIONAL {
+        var cascade = 0u;
+        while cascade < shadows.settings.cascade_count && view_depth > shadows.settings.cascade_splits[cascade] {
+            cascade++;
+        }
+        if cascade == shadows.settings.cascade_count {
+            return 1.0;
+        }
+        layer += cascade;
+    }
+    return shadow_pcf(layer, position, normal);
+}
+"#;
+
+const SHADOW_PASS_WGSL: &str = r#"
+@group(0) @binding(0) var<uniform> light_view_proj: mat4x4<f32>;
+
+struct InstanceInput {
+    @location(3) model_0: vec4<f32>,
+    @location(4) model_1: vec4<f32>,
+    @location(5) model_2: vec4<f32>,
+    @location(6) model_3: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) pos: vec4<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return light_view_proj * model * vec4<f32>(pos.xyz, 1.0);
+}
+"#;
+
+#[cfg(feature = "egui")]
+const SHADOW_DEBUG_WGSL: &str = r#"
+@group(0) @binding(0) var shadow_map: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(shadow_map));
+    let coords = vec2<u32>(min(in.uv * size, size - 1.0));
+    let depth = textureLoad(shadow_map, coords, 0);
+    return vec4<f32>(vec3<f32>(depth), 1.0);
+}
+"#;
+
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// maximum number of cascades of a directional light
+pub const MAX_CASCADES: u32 = 4;
+
+/// settings of the shadow maps, `resolution` and `layers` are fixed once the maps are created
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowConfig {
+    /// width and height of a shadow map
+    pub resolution: u32,
+    /// number of shadow maps, shared by all the lights
+    pub layers: u32,
+    /// shadow maps of a directional light, at most MAX_CASCADES
+    pub cascades: u32,
+    /// blend between uniform (0) and logarithmic (1) cascade splits
+    pub split_lambda: f32,
+    /// distance from the camera covered by the cascades
+    pub max_distance: f32,
+    /// bias subtracted from the depth before the comparison
+    pub depth_bias: f32,
+    /// offset of the sampled point along the normal, in texels
+    pub normal_bias: f32,
+    /// radius of the pcf kernel in texels, 0 for a single filtered tap
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            layers: 8,
+            cascades: MAX_CASCADES,
+            split_lambda: 0.75,
+            max_distance: 100.0,
+            depth_bias: 0.0005,
+            normal_bias: 1.5,
+            pcf_radius: 1,
+        }
+    }
+}
+
+impl ShadowConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = layers.max(1);
+        self
+    }
+
+    pub fn with_cascades(mut self, cascades: u32) -> Self {
+        self.cascades = cascades.clamp(1, MAX_CASCADES);
+        self
+    }
+
+    pub fn with_split_lambda(mut self, lambda: f32) -> Self {
+        self.split_lambda = lambda;
+        self
+    }
+
+    pub fn with_max_distance(mut self, distance: f32) -> Self {
+        self.max_distance = distance;
+        self
+    }
+
+    pub fn with_bias(mut self, depth_bias: f32, normal_bias: f32) -> Self {
+        self.depth_bias = depth_bias;
+        self.normal_bias = normal_bias;
+        self
+    }
+
+    pub fn with_pcf_radius(mut self, radius: u32) -> Self {
+        self.pcf_radius = radius;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+#[uniform(storage)]
+struct ShadowSettings {
+    cascade_splits: [f32; 4],
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: u32,
+    cascade_count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+#[uniform(storage)]
+struct ShadowLayer {
+    view_proj: [[f32; 4]; 4],
+    /// world size of a texel at w = 1
+    texel_size: f32,
+    _pad: [f32; 3],
+}
+
+/// offset between the light matrices of the shadow passes
+const PASS_STRIDE: u64 = 256;
+
+/// shadow maps of the lights, layers of a D2Array depth texture
+///
+/// a directional light takes one layer per cascade and a spot light one layer
+#[derive(Debug)]
+pub struct ShadowMaps {
+    pub config: ShadowConfig,
+    pub texture: wgpu::Texture,
+    /// one view per layer, to render into
+    pub layer_views: Vec<wgpu::TextureView>,
+    array_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    /// settings and layers, read when shading
+    buffer: wgpu::Buffer,
+    /// light matrix of each layer, read by the shadow passes
+    pass_buffer: wgpu::Buffer,
+    pass_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    /// lights casting shadows and their first layer
+    casters: Vec<(Light, u32)>,
+    cascades: u32,
+    #[cfg(feature = "egui")]
+    debug: std::sync::Arc<ShadowDebug>,
+    #[cfg(feature = "egui")]
+    debug_layer: u32,
+}
+
+impl ShadowMaps {
+    pub fn new(gpu: &Gpu, config: ShadowConfig) -> Self {
+        let device = &gpu.device;
+        let layers = config.layers.max(1);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow_maps"),
+            size: wgpu::Extent3d {
+                width: config.resolution,
+                height: config.resolution,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let layer_views: Vec<_> = (0..layers)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("shadow_map_layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("shadow_maps_view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadows_buffer"),
+            size: (std::mem::size_of::<ShadowSettings>()
+                + layers as usize * std::mem::size_of::<ShadowLayer>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pass_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow_pass_buffer"),
+            size: layers as u64 * PASS_STRIDE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pass_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow_pass_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(64),
+                },
+                count: None,
+            }],
+        });
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow_pass_bind_group"),
+            layout: &pass_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &pass_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(64),
+                }),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow_pass_shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADOW_PASS_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow_pass_pipeline_layout"),
+            bind_group_layouts: &[&pass_layout],
+            push_constant_ranges: &[],
+        });
+        let instance_layout = InstanceLayout::after::<Vertex, InstanceTransform>();
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow_pass_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), instance_layout.desc()],
+            },
+            fragment: None,
+            // no culling, so open meshes such as planes cast shadows
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: 1.5,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "egui")]
+        let debug = std::sync::Arc::new(ShadowDebug::new(gpu, &layer_views));
+
+        Self {
+            config,
+            texture,
+            layer_views,
+            array_view,
+            sampler,
+            buffer,
+            pass_buffer,
+            pass_bind_group,
+            pipeline,
+            casters: Vec::new(),
+            cascades: 0,
+            #[cfg(feature = "egui")]
+            debug,
+            #[cfg(feature = "egui")]
+            debug_layer: 0,
+        }
+    }
+
+    /// entries of the shadow maps in a bind group layout, starting at `first_binding`
+    pub fn layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// resources matching layout_entries
+    pub fn bind_group_entries(&self, first_binding: u32) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: self.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.array_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    /// give the first layer of each light casting shadows, None when it casts none or
+    /// when the layers are exhausted
+    pub fn assign(&mut self, lights: &[Light]) -> Vec<Option<u32>> {
+        self.cascades = self.config.cascades.clamp(1, MAX_CASCADES);
+        self.casters.clear();
+
+        let mut next = 0;
+        lights
+            .iter()
+            .map(|light| {
+                let needed = match light {
+                    Light::Directional(l) if l.cast_shadows => self.cascades,
+                    Light::Spot(l) if l.cast_shadows => 1,
+                    _ => return None,
+                };
+                if next + needed > self.layer_views.len() as u32 {
+                    return None;
+                }
+                self.casters.push((*light, next));
+                next += needed;
+                Some(next - needed)
+            })
+            .collect()
+    }
+
+    /// number of layers used by the lights of the last assign
+    pub fn active_layers(&self) -> u32 {
+        self.casters
+            .iter()
+            .map(|(light, _)| match light {
+                Light::Directional(_) => self.cascades,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// compute the light matrices, the cascades fit the view frustum of the camera
+    pub fn prepare(&self, gpu: &Gpu, camera: &CameraUniform) {
+        let resolution = self.config.resolution as f32;
+        let frustum = CameraFrustum::new(camera);
+        let far = frustum.far.min(self.config.max_distance);
+        // the logarithmic splits only make sense for a perspective depth distribution
+        let lambda = match frustum.perspective {
+            true => self.config.split_lambda,
+            false => 0.0,
+        };
+        let splits = cascade_splits(frustum.near, far, self.cascades, lambda);
+
+        let mut layers =
+            vec![<ShadowLayer as bytemuck::Zeroable>::zeroed(); self.layer_views.len()];
+        for (light, first) in &self.casters {
+            match light {
+                Light::Directional(l) => {
+                    let mut near = frustum.near;
+                    for (cascade, &split) in splits.iter().take(self.cascades as usize).enumerate()
+                    {
+                        let corners = frustum.corners(near, split);
+                        layers[*first as usize + cascade] =
+                            directional_layer(l.direction, &corners, resolution, far);
+                        near = split;
+                    }
+                }
+                Light::Spot(l) => {
+                    let outer = l.outer_angle.min(std::f32::consts::FRAC_PI_2 - 0.01);
+                    let view = Mat4::look_at_rh(
+                        l.position,
+                        l.position + l.direction,
+                        up_vector(l.direction),
+                    );
+                    let proj = Mat4::perspective_rh(2.0 * outer, 1.0, l.range * 0.01, l.range);
+                    layers[*first as usize] = ShadowLayer {
+                        view_proj: (proj * view).to_cols_array_2d(),
+                        texel_size: 2.0 * outer.tan() / resolution,
+                        _pad: [0.0; 3],
+                    };
+                }
+                Light::Point(_) => {}
+            }
+        }
+
+        let settings = ShadowSettings {
+            cascade_splits: splits,
+            depth_bias: self.config.depth_bias,
+            normal_bias: self.config.normal_bias,
+            pcf_radius: self.config.pcf_radius,
+            cascade_count: self.cascades,
+        };
+        let mut data = bytemuck::bytes_of(&settings).to_vec();
+        data.extend_from_slice(bytemuck::cast_slice(&layers));
+        gpu.queue.write_buffer(&self.buffer, 0, &data);
+
+        let mut pass_data = vec![0u8; layers.len() * PASS_STRIDE as usize];
+        for (layer, chunk) in layers
+            .iter()
+            .zip(pass_data.chunks_mut(PASS_STRIDE as usize))
+        {
+            chunk[..64].copy_from_slice(bytemuck::bytes_of(&layer.view_proj));
+        }
+        gpu.queue.write_buffer(&self.pass_buffer, 0, &pass_data);
+    }
+
+    /// begin the depth pass of a layer, with the pipeline and the light matrix bound
+    pub fn begin_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        layer: u32,
+    ) -> wgpu::RenderPass<'a> {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.layer_views[layer as usize],
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.pass_bind_group, &[layer * PASS_STRIDE as u32]);
+        rpass
+    }
+
+    /// render the active layers with the shadow casting meshes
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        casters: &[(&Mesh, &InstanceBuffer<InstanceTransform>)],
+    ) {
+        for layer in 0..self.active_layers() {
+            let mut rpass = self.begin_pass(encoder, layer);
+            for (mesh, instances) in casters {
+                mesh.draw_instanced(&mut rpass, instances);
+            }
+        }
+    }
+
+    /// settings sliders and a view of a shadow map
+    #[cfg(feature = "egui")]
+    pub fn debug_ui(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.config;
+        ui.add(
+            egui::Slider::new(&mut config.depth_bias, 0.0..=0.01)
+                .logarithmic(true)
+                .text("depth bias"),
+        );
+        ui.add(egui::Slider::new(&mut config.normal_bias, 0.0..=5.0).text("normal bias"));
+        ui.add(egui::Slider::new(&mut config.pcf_radius, 0..=4).text("pcf radius"));
+        ui.add(egui::Slider::new(&mut config.split_lambda, 0.0..=1.0).text("split lambda"));
+        ui.add(
+            egui::Slider::new(&mut config.max_distance, 1.0..=1000.0)
+                .logarithmic(true)
+                .text("max distance"),
+        );
+        ui.add(egui::Slider::new(&mut config.cascades, 1..=MAX_CASCADES).text("cascades"));
+
+        let last = self.layer_views.len() as u32 - 1;
+        ui.add(egui::Slider::new(&mut self.debug_layer, 0..=last).text("layer"));
+        let size = ui.available_width().min(256.0);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+        ui.painter().add(egui_wgpu::Callback::new_paint_callback(
+            rect,
+            ShadowDebugCallback {
+                debug: self.debug.clone(),
+                layer: self.debug_layer as usize,
+            },
+        ));
+    }
+}
+
+/// start of the cascades of an orthographic camera
+const MIN_ORTHOGRAPHIC_NEAR: f32 = 0.01;
+
+/// near and far planes of a camera and the corners of its near plane in view space
+struct CameraFrustum {
+    near: f32,
+    far: f32,
+    near_corners: [Vec3; 4],
+    perspective: bool,
+    inv_view: Mat4,
+}
+
+impl CameraFrustum {
+    fn new(camera: &CameraUniform) -> Self {
+        let proj = Mat4::from_cols_array_2d(&camera.proj);
+        let inv_proj = proj.inverse();
+        let unproject = |x: f32, y: f32, z: f32| inv_proj * Vec4::new(x, y, z, 1.0);
+
+        let near_corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+            let p = unproject(x, y, 0.0);
+            p.xyz() / p.w
+        });
+        let perspective = proj.w_axis.w == 0.0;
+        // orthographic cameras can have their near plane behind the eye (znear = -1000)
+        let near = match perspective {
+            true => -near_corners[0].z,
+            false => (-near_corners[0].z).max(MIN_ORTHOGRAPHIC_NEAR),
+        };
+        // infinite projections have w = 0 on the far plane
+        let far = unproject(0.0, 0.0, 1.0);
+        let far = if far.w.abs() < f32::EPSILON {
+            f32::INFINITY
+        } else {
+            -far.z / far.w
+        };
+
+        Self {
+            near,
+            far,
+            near_corners,
+            perspective,
+            inv_view: Mat4::from_cols_array_2d(&camera.view).inverse(),
+        }
+    }
+
+    /// world corners of the slice of the frustum between two view depths
+    fn corners(&self, near: f32, far: f32) -> [Vec3; 8] {
+        let at = |corner: Vec3, depth: f32| {
+            let p = if self.perspective {
+                corner * (depth / self.near)
+            } else {
+                corner.truncate().extend(-depth)
+            };
+            self.inv_view.transform_point3(p)
+        };
+        let mut corners = [Vec3::ZERO; 8];
+        for (i, &corner) in self.near_corners.iter().enumerate() {
+            corners[i] = at(corner, near);
+            corners[i + 4] = at(corner, far);
+        }
+        corners
+    }
+}
+
+/// far distance of each cascade, mixing uniform and logarithmic splits
+fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> [f32; 4] {
+    let mut splits = [far; 4];
+    for (i, split) in splits.iter_mut().enumerate().take(count as usize) {
+        let t = (i + 1) as f32 / count as f32;
+        let uniform = near + (far - near) * t;
+        // the logarithmic splits need 0 < near
+        *split = match near > 0.0 {
+            true => lambda * near * (far / near).powf(t) + (1.0 - lambda) * uniform,
+            false => uniform,
+        };
+    }
+    splits
+}
+
+fn up_vector(direction: Vec3) -> Vec3 {
+    if direction.normalize().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
+
+/// orthographic projection around the bounding sphere of a cascade, snapped to the texels so
+/// the shadows do not shimmer when the camera moves
+fn directional_layer(
+    direction: Vec3,
+    corners: &[Vec3; 8],
+    resolution: f32,
+    caster_distance: f32,
+) -> ShadowLayer {
+    let direction = direction.normalize();
+    let center = corners.iter().sum::<Vec3>() / 8.0;
+    let radius = corners
+        .iter()
+        .map(|c| c.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // casters up to caster_distance in front of the sphere stay in the depth range
+    let eye = center - direction * (radius + caster_distance);
+    let view = Mat4::look_at_rh(eye, center, up_vector(direction));
+    let mut proj = Mat4::orthographic_rh(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        0.0,
+        2.0 * radius + caster_distance,
+    );
+
+    let origin = (proj * view * Vec4::W).xy() * resolution / 2.0;
+    let offset = (origin.round() - origin) * 2.0 / resolution;
+    proj.w_axis.x += offset.x;
+    proj.w_axis.y += offset.y;
+
+    ShadowLayer {
+        view_proj: (proj * view).to_cols_array_2d(),
+        texel_size: 2.0 * radius / resolution,
+        _pad: [0.0; 3],
+    }
+}
+
+/// pipeline drawing a shadow map in an egui paint callback
+#[cfg(feature = "egui")]
+#[derive(Debug)]
+struct ShadowDebug {
+    pipeline: wgpu::RenderPipeline,
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+#[cfg(feature = "egui")]
+impl ShadowDebug {
+    fn new(gpu: &Gpu, layer_views: &[wgpu::TextureView]) -> Self {
+        let device = &gpu.device;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow_debug_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let bind_groups = layer_views
+            .iter()
+            .map(|view| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("shadow_debug_bind_group"),
+                    layout: &layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    }],
+                })
+            })
+            .collect();
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow_debug_shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADOW_DEBUG_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow_debug_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        // egui draws to the surface
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow_debug_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(gpu.get_surface_texture_format().into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_groups,
+        }
+    }
+}
+
+#[cfg(feature = "egui")]
+struct ShadowDebugCallback {
+    debug: std::sync::Arc<ShadowDebug>,
+    layer: usize,
+}
+
+#[cfg(feature = "egui")]
+impl egui_wgpu::CallbackTrait for ShadowDebugCallback {
+    fn paint<'a>(
+        &'a self,
+        _info: egui::PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        _callback_resources: &'a egui_wgpu::CallbackResources,
+    ) {
+        render_pass.set_pipeline(&self.debug.pipeline);
+        render_pass.set_bind_group(0, &self.debug.bind_groups[self.layer], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_without_positive_near_are_uniform() {
+        let splits = cascade_splits(-1000.0, 1000.0, 4, 0.75);
+        assert_eq!(splits, [-500.0, 0.0, 500.0, 1000.0]);
+        let splits = cascade_splits(0.1, 100.0, 2, 1.0);
+        assert!((splits[0] - 0.1 * 1000f32.sqrt()).abs() < 1e-3);
+        assert_eq!(splits[1], 100.0);
+    }
+}
@@ -0,0 +1,462 @@
+use std::ops::Range;
+
+use glam::{Mat4, Vec2};
+use wgpu::util::DeviceExt;
+
+use crate::gpu::Gpu;
+use crate::graphics::{Rect, Texture, Vertex2D, QUAD, QUAD_INDICES};
+use crate::instance::{InstanceBuffer, InstanceLayout};
+use crate::layout::{GpuUniform, VertexLayout};
+use crate::mesh::{Indices, Mesh};
+
+const SPRITE_WGSL: &str = r#"
+struct Globals {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1) var sprite_sampler: sampler;
+
+struct VertexInput {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(2) position: vec2<f32>,
+    @location(3) size: vec2<f32>,
+    @location(4) origin: vec2<f32>,
+    @location(5) rotation: f32,
+    @location(6) uv_rect: vec4<f32>,
+    @location(7) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    // the quad uvs go from the top left to the bottom right corner, like pixel space
+    let local = (vertex.uv - instance.origin) * instance.size;
+    let c = cos(instance.rotation);
+    let s = sin(instance.rotation);
+    let world = instance.position + vec2<f32>(local.x * c - local.y * s, local.x * s + local.y * c);
+
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.uv = instance.uv_rect.xy + vertex.uv * instance.uv_rect.zw;
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+}
+"#;
+
+/// how a sprite is composited with what is behind it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum BlendMode {
+    #[default]
+    Alpha,
+    /// colors already multiplied by their alpha
+    Premultiplied,
+    Additive,
+    Multiply,
+    Opaque,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 5] = [
+        BlendMode::Alpha,
+        BlendMode::Premultiplied,
+        BlendMode::Additive,
+        BlendMode::Multiply,
+        BlendMode::Opaque,
+    ];
+
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendMode::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+            BlendMode::Opaque => wgpu::BlendState::REPLACE,
+        }
+    }
+}
+
+/// texture registered in a SpriteBatch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    pub texture: TextureId,
+    /// part of the texture in texels, the whole texture when None
+    pub source: Option<Rect>,
+    /// pixel position of the origin, (0, 0) is the top left corner of the target
+    pub position: Vec2,
+    /// pivot of the rotation and scale, (0, 0) is the top left corner of the sprite and (1, 1) the bottom right one
+    pub origin: Vec2,
+    /// clockwise rotation in radians
+    pub rotation: f32,
+    pub scale: Vec2,
+    pub color: [f32; 4],
+    /// sprites of lower layers are drawn first
+    pub layer: i32,
+    pub blend: BlendMode,
+}
+
+impl Sprite {
+    pub fn new(texture: TextureId) -> Self {
+        Self {
+            texture,
+            source: None,
+            position: Vec2::ZERO,
+            origin: Vec2::splat(0.5),
+            rotation: 0.0,
+            scale: Vec2::ONE,
+            color: [1.0; 4],
+            layer: 0,
+            blend: BlendMode::Alpha,
+        }
+    }
+
+    pub fn with_source(mut self, source: Rect) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_position(mut self, position: impl Into<Vec2>) -> Self {
+        self.position = position.into();
+        self
+    }
+
+    pub fn with_origin(mut self, origin: impl Into<Vec2>) -> Self {
+        self.origin = origin.into();
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: impl Into<Vec2>) -> Self {
+        self.scale = scale.into();
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(instance)]
+struct SpriteInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    origin: [f32; 2],
+    rotation: f32,
+    uv_rect: [f32; 4],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct SpriteGlobals {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[derive(Debug)]
+struct SpriteTexture {
+    bind_group: wgpu::BindGroup,
+    size: Vec2,
+}
+
+/// consecutive instances drawn with one draw call
+#[derive(Debug, Clone)]
+struct Batch {
+    texture: TextureId,
+    blend: BlendMode,
+    instances: Range<u32>,
+}
+
+/// draws sprites queued during a frame, batched by blend mode and texture
+///
+/// queue sprites with draw and upload them with prepare during update, then record them with render
+#[derive(Debug)]
+pub struct SpriteBatch {
+    /// transform applied before the pixel space projection, e.g. a 2D camera
+    pub view: Mat4,
+    pipelines: Vec<wgpu::RenderPipeline>,
+    texture_layout: wgpu::BindGroupLayout,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    quad: Mesh,
+    instance_buffer: InstanceBuffer<SpriteInstance>,
+    textures: Vec<SpriteTexture>,
+    sprites: Vec<Sprite>,
+    batches: Vec<Batch>,
+}
+
+impl SpriteBatch {
+    /// sprite batch rendering to the scene target
+    pub fn new(gpu: &Gpu) -> Self {
+        Self::with_format(gpu, gpu.get_scene_format())
+    }
+
+    /// sprite batch rendering to targets of the given format
+    pub fn with_format(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let device = &gpu.device;
+
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite_globals_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite_texture_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sprite_globals_buffer"),
+            contents: bytemuck::bytes_of(&SpriteGlobals {
+                view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite_globals_bind_group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sprite_shader"),
+            source: wgpu::ShaderSource::Wgsl(SPRITE_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sprite_pipeline_layout"),
+            bind_group_layouts: &[&globals_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let instance_layout = InstanceLayout::after::<Vertex2D, SpriteInstance>();
+        let pipelines = BlendMode::ALL
+            .iter()
+            .map(|blend| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("sprite_pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[Vertex2D::desc(), instance_layout.desc()],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: Some(blend.blend_state()),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    // rotations and negative scales can flip the winding
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            })
+            .collect();
+
+        let quad = Mesh::new(device, QUAD, &Indices::compact(QUAD_INDICES.to_vec()));
+
+        Self {
+            view: Mat4::IDENTITY,
+            pipelines,
+            texture_layout,
+            globals_buffer,
+            globals_bind_group,
+            quad,
+            instance_buffer: InstanceBuffer::with_capacity(device, 256),
+            textures: vec![],
+            sprites: vec![],
+            batches: vec![],
+        }
+    }
+
+    /// make a texture usable by the sprites
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: &Texture) -> TextureId {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite_texture_bind_group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+        self.textures.push(SpriteTexture {
+            bind_group,
+            size: Vec2::new(texture.size.width as f32, texture.size.height as f32),
+        });
+        TextureId(self.textures.len() - 1)
+    }
+
+    /// queue a sprite for the next prepare
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// sort and upload the queued sprites, with a projection covering the scene target
+    pub fn prepare(&mut self, gpu: &Gpu) {
+        let (width, height) = gpu.scene_size();
+        self.prepare_with_size(gpu, (width as f32, height as f32));
+    }
+
+    /// sort and upload the queued sprites, with a projection covering size pixels
+    pub fn prepare_with_size(&mut self, gpu: &Gpu, size: (f32, f32)) {
+        let proj = Mat4::orthographic_rh(0.0, size.0, size.1, 0.0, -1.0, 1.0);
+        let globals = SpriteGlobals {
+            view_proj: (proj * self.view).to_cols_array_2d(),
+        };
+        gpu.queue
+            .write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        // stable, so sprites of a layer keep their submission order within a batch
+        self.sprites
+            .sort_by_key(|sprite| (sprite.layer, sprite.blend, sprite.texture));
+
+        self.batches.clear();
+        let mut instances = Vec::with_capacity(self.sprites.len());
+        for sprite in self.sprites.drain(..) {
+            let texture_size = self.textures[sprite.texture.0].size;
+            let source =
+                sprite
+                    .source
+                    .unwrap_or(Rect::new(0.0, 0.0, texture_size.x, texture_size.y));
+            let size = Vec2::new(source.w, source.h) * sprite.scale;
+            instances.push(SpriteInstance {
+                position: sprite.position.to_array(),
+                size: size.to_array(),
+                origin: sprite.origin.to_array(),
+                rotation: sprite.rotation,
+                uv_rect: [
+                    source.x / texture_size.x,
+                    source.y / texture_size.y,
+                    source.w / texture_size.x,
+                    source.h / texture_size.y,
+                ],
+                color: sprite.color,
+            });
+
+            let index = instances.len() as u32 - 1;
+            match self.batches.last_mut() {
+                Some(batch) if batch.texture == sprite.texture && batch.blend == sprite.blend => {
+                    batch.instances.end = index + 1;
+                }
+                _ => self.batches.push(Batch {
+                    texture: sprite.texture,
+                    blend: sprite.blend,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+        self.instance_buffer
+            .update(&gpu.device, &gpu.queue, &instances);
+    }
+
+    /// record the sprites uploaded by the last prepare
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if self.instance_buffer.is_empty() {
+            return;
+        }
+        rpass.set_bind_group(0, &self.globals_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.quad.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice());
+        rpass.set_index_buffer(self.quad.index_buffer.slice(..), self.quad.index_format);
+        for batch in &self.batches {
+            let pipeline = BlendMode::ALL
+                .iter()
+                .position(|b| *b == batch.blend)
+                .unwrap();
+            rpass.set_pipeline(&self.pipelines[pipeline]);
+            rpass.set_bind_group(1, &self.textures[batch.texture.0].bind_group, &[]);
+            rpass.draw_indexed(0..self.quad.index_count, 0, batch.instances.clone());
+        }
+    }
+
+    /// number of draw calls recorded by render
+    pub fn draw_calls(&self) -> usize {
+        self.batches.len()
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{Font as _, FontArc, GlyphId, PxScale, ScaleFont};
+use glam::{Mat4, Vec2, Vec4};
+use log::warn;
+
+use crate::atlas::{AtlasHandle, AtlasLayout, PackingAlgorithm, TextureAtlas};
+use crate::gpu::Gpu;
+use crate::layout::VertexLayout;
+
+const TEXT_WGSL: &str = r#"
+@group(0) @binding(0) var glyph_texture: texture_2d<f32>;
+@group(0) @binding(1) var glyph_sampler: sampler;
+
+struct VertexInput {
+    @location(0) pos: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = in.pos;
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(glyph_texture, glyph_sampler, in.uv).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+"#;
+
+#[derive(Debug)]
+pub enum TextError {
+    Io(PathBuf, std::io::Error),
+    InvalidFont,
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextError::Io(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            TextError::InvalidFont => write!(f, "invalid TTF/OTF font data"),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// TTF or OTF font
+#[derive(Debug, Clone)]
+pub struct Font {
+    font: FontArc,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, TextError> {
+        let font = FontArc::try_from_vec(data).map_err(|_| TextError::InvalidFont)?;
+        Ok(Self { font })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TextError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| TextError::Io(path.to_path_buf(), e))?;
+        Self::from_bytes(data)
+    }
+
+    /// distance between the baselines of two lines
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.height() + scaled.line_gap()
+    }
+
+    /// place the glyphs of text left to right, breaking lines at '\n' and, with a max width, between words
+    pub fn layout(
+        &self,
+        text: &str,
+        size: f32,
+        max_width: Option<f32>,
+        align: Align,
+    ) -> TextLayout {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let mut lines: Vec<Vec<LineGlyph>> = vec![];
+
+        for paragraph in text.split('\n') {
+            let mut line: Vec<LineGlyph> = vec![];
+            let mut pen = 0.0;
+            let mut prev = None;
+            // index of the glyph following the last whitespace
+            let mut break_at = None;
+
+            for c in paragraph.chars().filter(|c| !c.is_control()) {
+                let id = scaled.glyph_id(c);
+                if let Some(prev) = prev {
+                    pen += scaled.kern(prev, id);
+                }
+                let advance = scaled.h_advance(id);
+
+                let overflow = max_width.is_some_and(|w| pen + advance > w);
+                if overflow && !c.is_whitespace() && !line.is_empty() {
+                    match break_at {
+                        Some(b) if b < line.len() => {
+                            // move the current word to the next line
+                            let mut rest = line.split_off(b);
+                            let shift = rest[0].x;
+                            rest.iter_mut().for_each(|g| g.x -= shift);
+                            lines.push(line);
+                            line = rest;
+                            pen -= shift;
+                        }
+                        _ => {
+                            lines.push(line);
+                            line = vec![];
+                            pen = 0.0;
+                        }
+                    }
+                    break_at = None;
+                }
+
+                line.push(LineGlyph {
+                    id,
+                    x: pen,
+                    advance,
+                    whitespace: c.is_whitespace(),
+                });
+                pen += advance;
+                prev = Some(id);
+                if c.is_whitespace() {
+                    break_at = Some(line.len());
+                }
+            }
+            lines.push(line);
+        }
+
+        let line_width = |line: &[LineGlyph]| {
+            line.iter()
+                .rev()
+                .find(|g| !g.whitespace)
+                .map_or(0.0, |g| g.x + g.advance)
+        };
+        let width =
+            max_width.unwrap_or_else(|| lines.iter().map(|l| line_width(l)).fold(0.0, f32::max));
+        let line_height = scaled.height() + scaled.line_gap();
+
+        let mut glyphs = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            let offset = align.factor() * (width - line_width(line));
+            let baseline = scaled.ascent() + i as f32 * line_height;
+            glyphs.extend(line.iter().filter(|g| !g.whitespace).map(|g| LayoutGlyph {
+                id: g.id.0,
+                position: Vec2::new(offset + g.x, baseline),
+            }));
+        }
+
+        TextLayout {
+            glyphs,
+            size: Vec2::new(width, lines.len() as f32 * line_height),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineGlyph {
+    id: GlyphId,
+    x: f32,
+    advance: f32,
+    whitespace: bool,
+}
+
+/// glyph placed by Font::layout
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutGlyph {
+    pub id: u16,
+    /// pen position on the baseline, relative to the top left corner of the text (y down)
+    pub position: Vec2,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<LayoutGlyph>,
+    /// width and height of the text block
+    pub size: Vec2,
+}
+
+/// horizontal alignment of the lines, and of the text around its position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    fn factor(self) -> f32 {
+        match self {
+            Align::Left => 0.0,
+            Align::Center => 0.5,
+            Align::Right => 1.0,
+        }
+    }
+}
+
+/// vertical alignment of the text around its position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VerticalAlign {
+    fn factor(self) -> f32 {
+        match self {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Middle => 0.5,
+            VerticalAlign::Bottom => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextSpace {
+    /// position and size in pixels, (0, 0) is the top left corner of the target
+    Screen,
+    /// text on the xy plane of the transform (x right, y up), position and size in world units
+    World(Mat4),
+}
+
+/// font registered in a TextRenderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub content: String,
+    pub font: FontId,
+    pub position: Vec2,
+    pub size: f32,
+    pub color: [f32; 4],
+    pub align: Align,
+    pub vertical_align: VerticalAlign,
+    /// lines are wrapped between words to fit this width
+    pub max_width: Option<f32>,
+    pub space: TextSpace,
+}
+
+impl Text {
+    pub fn new(font: FontId, content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            font,
+            position: Vec2::ZERO,
+            size: 16.0,
+            color: [1.0; 4],
+            align: Align::Left,
+            vertical_align: VerticalAlign::Top,
+            max_width: None,
+            space: TextSpace::Screen,
+        }
+    }
+
+    pub fn with_position(mut self, position: impl Into<Vec2>) -> Self {
+        self.position = position.into();
+        self
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_vertical_align(mut self, vertical_align: VerticalAlign) -> Self {
+        self.vertical_align = vertical_align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_space(mut self, space: TextSpace) -> Self {
+        self.space = space;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+struct TextVertex {
+    /// clip space
+    pos: [f32; 4],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+/// rasterized glyph in the atlas
+#[derive(Debug, Clone, Copy)]
+struct CachedGlyph {
+    handle: AtlasHandle,
+    /// top left corner relative to the pen position, in pixels at the raster size
+    offset: Vec2,
+    size: Vec2,
+}
+
+/// draws text queued during a frame, with glyphs rasterized on the cpu into an atlas
+///
+/// queue texts with draw and upload them with prepare during update, then record them with render
+#[derive(Debug)]
+pub struct TextRenderer {
+    /// pixel size world space glyphs are rasterized at
+    pub world_raster_size: f32,
+    fonts: Vec<Font>,
+    atlas: TextureAtlas,
+    glyphs: HashMap<(usize, u16, u32), Option<CachedGlyph>>,
+    pipeline: wgpu::RenderPipeline,
+    page_layout: wgpu::BindGroupLayout,
+    page_bind_groups: Vec<wgpu::BindGroup>,
+    vertex_buffer: wgpu::Buffer,
+    vertex_capacity: u64,
+    texts: Vec<Text>,
+    batches: Vec<(usize, Range<u32>)>,
+}
+
+impl TextRenderer {
+    /// text renderer drawing to the scene target
+    pub fn new(gpu: &Gpu) -> Self {
+        Self::with_format(gpu, gpu.get_scene_format())
+    }
+
+    /// text renderer drawing to targets of the given format
+    pub fn with_format(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let device = &gpu.device;
+
+        let page_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("text_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("text_shader"),
+            source: wgpu::ShaderSource::Wgsl(TEXT_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("text_pipeline_layout"),
+            bind_group_layouts: &[&page_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("text_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[TextVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let atlas = TextureAtlas::with_format(
+            AtlasLayout::new((1024, 1024))
+                .with_algorithm(PackingAlgorithm::Shelf)
+                .with_padding(2)
+                .with_extrusion(0),
+            wgpu::TextureFormat::R8Unorm,
+        );
+
+        let vertex_capacity = 1024;
+        Self {
+            world_raster_size: 64.0,
+            fonts: vec![],
+            atlas,
+            glyphs: HashMap::new(),
+            pipeline,
+            page_layout,
+            page_bind_groups: vec![],
+            vertex_buffer: Self::create_vertex_buffer(device, vertex_capacity),
+            vertex_capacity,
+            texts: vec![],
+            batches: vec![],
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("text_vertex_buffer"),
+            size: capacity * std::mem::size_of::<TextVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    pub fn font(&self, id: FontId) -> &Font {
+        &self.fonts[id.0]
+    }
+
+    /// queue a text for the next prepare
+    pub fn draw(&mut self, text: Text) {
+        self.texts.push(text);
+    }
+
+    /// rasterize and upload the queued texts, view_proj is used for world space texts
+    pub fn prepare(&mut self, gpu: &Gpu, view_proj: Mat4) {
+        let (width, height) = gpu.scene_size();
+        self.prepare_with_size(gpu, (width as f32, height as f32), view_proj);
+    }
+
+    /// rasterize and upload the queued texts, with screen space texts covering size pixels
+    pub fn prepare_with_size(&mut self, gpu: &Gpu, size: (f32, f32), view_proj: Mat4) {
+        let screen = Mat4::orthographic_rh(0.0, size.0, size.1, 0.0, -1.0, 1.0);
+
+        // vertices of each atlas page
+        let mut pages: Vec<Vec<TextVertex>> = vec![];
+        for text in std::mem::take(&mut self.texts) {
+            let (raster_size, transform) = match text.space {
+                TextSpace::Screen => (text.size.round().max(1.0), screen),
+                TextSpace::World(model) => (
+                    self.world_raster_size,
+                    view_proj * model * Mat4::from_scale(glam::vec3(1.0, -1.0, 1.0)),
+                ),
+            };
+            let scale = text.size / raster_size;
+
+            let font = self.fonts[text.font.0].clone();
+            let layout = font.layout(&text.content, text.size, text.max_width, text.align);
+            let anchor = layout.size * Vec2::new(text.align.factor(), text.vertical_align.factor());
+            let origin = match text.space {
+                TextSpace::Screen => text.position - anchor,
+                // the transform flips y so the layout stays y down
+                TextSpace::World(_) => {
+                    Vec2::new(text.position.x - anchor.x, -text.position.y - anchor.y)
+                }
+            };
+
+            for glyph in &layout.glyphs {
+                let Some(cached) = self.glyph(text.font, glyph.id, raster_size, gpu) else {
+                    continue;
+                };
+                let mut min = origin + glyph.position + cached.offset * scale;
+                if text.space == TextSpace::Screen {
+                    min = min.round();
+                }
+                let max = min + cached.size * scale;
+
+                let uv = self.atlas.uv_rect(cached.handle);
+                let corner = |x: f32, y: f32, u: f32, v: f32| TextVertex {
+                    pos: (transform * Vec4::new(x, y, 0.0, 1.0)).to_array(),
+                    uv: [u, v],
+                    color: text.color,
+                };
+                let (u0, v0, u1, v1) = (uv.x, uv.y, uv.x + uv.w, uv.y + uv.h);
+                let quad = [
+                    corner(min.x, min.y, u0, v0),
+                    corner(min.x, max.y, u0, v1),
+                    corner(max.x, max.y, u1, v1),
+                    corner(min.x, min.y, u0, v0),
+                    corner(max.x, max.y, u1, v1),
+                    corner(max.x, min.y, u1, v0),
+                ];
+
+                let page = self.atlas.layout.entry(cached.handle).page;
+                if pages.len() <= page {
+                    pages.resize_with(page + 1, Vec::new);
+                }
+                pages[page].extend_from_slice(&quad);
+            }
+        }
+
+        // bind groups of the pages created while rasterizing
+        while self.page_bind_groups.len() < self.atlas.pages.len() {
+            let page = &self.atlas.pages[self.page_bind_groups.len()];
+            let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("text_bind_group"),
+                layout: &self.page_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&page.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&page.sampler),
+                    },
+                ],
+            });
+            self.page_bind_groups.push(bind_group);
+        }
+
+        self.batches.clear();
+        let mut vertices = vec![];
+        for (page, page_vertices) in pages.into_iter().enumerate() {
+            if page_vertices.is_empty() {
+                continue;
+            }
+            let start = vertices.len() as u32;
+            vertices.extend(page_vertices);
+            self.batches.push((page, start..vertices.len() as u32));
+        }
+
+        if vertices.len() as u64 > self.vertex_capacity {
+            self.vertex_capacity = (vertices.len() as u64).next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(&gpu.device, self.vertex_capacity);
+        }
+        gpu.queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    /// rasterize a glyph the first time it is used, None for glyphs without outline
+    fn glyph(&mut self, font: FontId, id: u16, size: f32, gpu: &Gpu) -> Option<CachedGlyph> {
+        let key = (font.0, id, size as u32);
+        if let Some(cached) = self.glyphs.get(&key) {
+            return *cached;
+        }
+
+        let glyph = GlyphId(id).with_scale(PxScale::from(size));
+        let cached = self.fonts[font.0]
+            .font
+            .outline_glyph(glyph)
+            .and_then(|outline| {
+                let bounds = outline.px_bounds();
+                let (w, h) = (bounds.width() as u32, bounds.height() as u32);
+                let mut coverage = vec![0; (w * h) as usize];
+                outline.draw(|x, y, c| {
+                    if x < w && y < h {
+                        coverage[(y * w + x) as usize] = (c.clamp(0.0, 1.0) * 255.0) as u8;
+                    }
+                });
+                match self.atlas.add(w, h, &coverage, &gpu.device, &gpu.queue) {
+                    Ok(handle) => Some(CachedGlyph {
+                        handle,
+                        offset: Vec2::new(bounds.min.x, bounds.min.y),
+                        size: Vec2::new(w as f32, h as f32),
+                    }),
+                    Err(e) => {
+                        warn!(target: "text", "glyph {id} not rasterized: {e}");
+                        None
+                    }
+                }
+            });
+        self.glyphs.insert(key, cached);
+        cached
+    }
+
+    /// record the texts uploaded by the last prepare
+    pub fn render<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for (page, vertices) in &self.batches {
+            rpass.set_bind_group(0, &self.page_bind_groups[*page], &[]);
+            rpass.draw(vertices.clone(), 0..1);
+        }
+    }
+}
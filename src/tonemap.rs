@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use crate::gpu::Gpu;
+use crate::layout::GpuUniform;
+use crate::render_target::{RenderTarget, RenderTargetBuilder, RenderTargetTextures};
+
+const TONEMAP_WGSL: &str = r#"
+struct Tonemap {
+    exposure: f32,
+    tonemapper: u32,
+    // the surface is not srgb, the shader encodes the output
+    encode_srgb: u32,
+}
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> tonemap: Tonemap;
+
+// fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// ACES fitted by Stephen Hill, sRGB to ACES AP1 and back around the RRT + ODT fit
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// AgX with the default contrast curve, from the fit of Benjamin Wrensch
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * color;
+    v = clamp(log2(max(v, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+
+    let x2 = v * v;
+    let x4 = x2 * x2;
+    v = 15.5 * x4 * x2 - 40.14 * x4 * v + 31.96 * x4 - 6.868 * x2 * v + 0.4298 * x2 + 0.1191 * v - 0.00232;
+
+    // the curve gives display values, back to linear for the srgb surface
+    v = outset * v;
+    return pow(max(v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(scene, vec2<i32>(position.xy), 0);
+    let color = max(hdr.rgb * exp2(tonemap.exposure), vec3<f32>(0.0));
+
+    // values of the Tonemapper enum
+    var mapped: vec3<f32>;
+    switch tonemap.tonemapper {
+        case 1u: { mapped = reinhard(color); }
+        case 2u: { mapped = aces(color); }
+        case 3u: { mapped = agx(color); }
+        default: { mapped = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)); }
+    }
+
+    if tonemap.encode_srgb != 0u {
+        mapped = linear_to_srgb(mapped);
+    }
+    return vec4<f32>(mapped, 1.0);
+}
+"#;
+
+/// format of the scene target of an hdr app
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// curve mapping the hdr colors to the [0, 1] range of the surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tonemapper {
+    /// clamp the colors
+    None = 0,
+    Reinhard = 1,
+    #[default]
+    Aces = 2,
+    AgX = 3,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 4] = [
+        Tonemapper::None,
+        Tonemapper::Reinhard,
+        Tonemapper::Aces,
+        Tonemapper::AgX,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemapper::None => "none",
+            Tonemapper::Reinhard => "reinhard",
+            Tonemapper::Aces => "aces",
+            Tonemapper::AgX => "agx",
+        }
+    }
+}
+
+/// settings applied when the scene target is resolved to the surface
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Tonemapping {
+    /// exposure in stops, the colors are scaled by 2^exposure
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+}
+
+impl Tonemapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_tonemapper(mut self, tonemapper: Tonemapper) -> Self {
+        self.tonemapper = tonemapper;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, GpuUniform)]
+struct TonemapUniform {
+    exposure: f32,
+    tonemapper: u32,
+    encode_srgb: u32,
+}
+
+/// hdr scene target and the pass tonemapping it to the surface
+#[derive(Debug)]
+pub struct TonemapRenderer {
+    pub target: RenderTarget,
+    /// textures of the target bound to the bind group
+    textures: Arc<RenderTargetTextures>,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    encode_srgb: bool,
+}
+
+impl TonemapRenderer {
+    /// scene target of Gpu::scene_size, resolved to the surface format
+    pub fn new(gpu: &Gpu) -> Self {
+        let device = &gpu.device;
+        let surface_format = gpu.get_surface_texture_format();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tonemap_buffer"),
+            size: std::mem::size_of::<TonemapUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let target = RenderTargetBuilder::new().with_color(HDR_FORMAT).build(gpu);
+        let textures = target.textures();
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &textures, &buffer);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tonemap_shader"),
+            source: wgpu::ShaderSource::Wgsl(TONEMAP_WGSL.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tonemap_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("tonemap_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(surface_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            target,
+            textures,
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            buffer,
+            encode_srgb: !surface_format.is_srgb(),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        textures: &RenderTargetTextures,
+        buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(textures.view(0)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// follow the size of the scene target, the target may already be resized by the app
+    pub fn resize(&mut self, gpu: &Gpu) {
+        self.target.resize(gpu);
+        let textures = self.target.textures();
+        if Arc::ptr_eq(&textures, &self.textures) {
+            return;
+        }
+        self.bind_group = Self::create_bind_group(
+            &gpu.device,
+            &self.bind_group_layout,
+            &textures,
+            &self.buffer,
+        );
+        self.textures = textures;
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        self.textures.view(0)
+    }
+
+    /// write the tonemapped scene to `output`
+    pub fn render(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        settings: &Tonemapping,
+    ) {
+        let uniform = TonemapUniform {
+            exposure: settings.exposure,
+            tonemapper: settings.tonemapper as u32,
+            encode_srgb: self.encode_srgb as u32,
+        };
+        gpu.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tonemap_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
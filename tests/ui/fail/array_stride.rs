@@ -0,0 +1,9 @@
+use wgpu_sandbox2::layout::GpuUniform;
+
+#[repr(C)]
+#[derive(GpuUniform)]
+struct Weights {
+    values: [f32; 8],
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use wgpu_sandbox2::layout::VertexLayout;
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct Vertex {
+    pos: [f32; 3],
+    #[location(0)]
+    uv: [f32; 2],
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use wgpu_sandbox2::layout::GpuUniform;
+
+#[repr(C, align = 16)]
+#[derive(GpuUniform)]
+struct Light {
+    position: [f32; 4],
+}
+
+fn main() {}
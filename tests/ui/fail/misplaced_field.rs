@@ -0,0 +1,10 @@
+use wgpu_sandbox2::layout::GpuUniform;
+
+#[repr(C)]
+#[derive(GpuUniform)]
+struct Light {
+    intensity: f32,
+    position: [f32; 3],
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use wgpu_sandbox2::layout::GpuUniform;
+
+#[repr(C)]
+#[derive(GpuUniform)]
+struct Light {
+    position: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+}
+
+fn main() {}
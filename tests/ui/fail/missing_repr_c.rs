@@ -0,0 +1,8 @@
+use wgpu_sandbox2::layout::GpuUniform;
+
+#[derive(GpuUniform)]
+struct Light {
+    position: [f32; 4],
+}
+
+fn main() {}
@@ -0,0 +1,32 @@
+use wgpu_sandbox2::layout::{GpuUniform, WgslType};
+
+#[repr(C)]
+#[derive(GpuUniform)]
+struct Light {
+    position: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+    _pad: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(GpuUniform)]
+struct Camera {
+    view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(GpuUniform)]
+#[uniform(storage)]
+struct Particles {
+    positions: [[f32; 2]; 8],
+    count: u32,
+    _pad: u32,
+}
+
+fn main() {
+    assert_eq!(Light::ALIGN, 16);
+    assert_eq!(Camera::SIZE, 80);
+    assert_eq!(Particles::wgsl_members()[0].1, "array<vec2<f32>, 8>");
+}
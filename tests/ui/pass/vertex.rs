@@ -0,0 +1,17 @@
+use wgpu_sandbox2::layout::VertexLayout;
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct Vertex {
+    pos: [f32; 3],
+    #[location(4)]
+    uv: [f32; 2],
+    #[format(Unorm8x4)]
+    color: [u8; 4],
+}
+
+fn main() {
+    let locations: Vec<_> = Vertex::ATTRIBUTES.iter().map(|a| a.shader_location).collect();
+    assert_eq!(locations, [0, 4, 5]);
+    assert_eq!(Vertex::ATTRIBUTES[2].offset, 20);
+}